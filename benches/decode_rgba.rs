@@ -0,0 +1,63 @@
+//! Compares decoding an asset straight into RGBA8 through a color LUT
+//! against the current path of decoding into an IndexedImage
+//! and then looking up the palette for every pixel.
+//!
+//! run with `cargo bench --bench decode_rgba`
+
+use std::{hint::black_box, time::Instant};
+use image::{Pixel, Rgba, RgbaImage};
+use sixteenbit_encoding::{
+    encodings::{rle_to_indexed, rle_to_rgba, OneByteRle, RgbaLut},
+    types::{ColorIndex, IndexedImage, Palette},
+};
+
+/// the sample character from the readme
+const SAMPLE: &str = "481220042001216221016041604160016040c060c040600166016122610280628001608660018403a403a002a003a002a002210221";
+const SIZE: usize = 16;
+const SCALE: usize = 4;
+const ITERATIONS: u32 = 20_000;
+
+fn bench(name: &str, mut decode: impl FnMut()) {
+    // warm up caches before timing
+    for _ in 0..ITERATIONS / 10 {
+        decode();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        decode();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<24} {:>10.2?} per decode ({:.0} decodes/s)",
+        elapsed / ITERATIONS,
+        ITERATIONS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let rle = OneByteRle::new_with_bytes(hex::decode(SAMPLE).unwrap()).unwrap();
+    let palette = Palette::default();
+    let lut = RgbaLut::new(&palette);
+
+    let mut indexed_out = RgbaImage::new((SIZE * SCALE) as u32, (SIZE * SCALE) as u32);
+    bench("indexed + palette", || {
+        let image: IndexedImage<{ SIZE * SIZE }, SIZE> = rle_to_indexed(black_box(&rle), 0);
+        for (x, y, p) in indexed_out.enumerate_pixels_mut() {
+            let color = image[(x as usize / SCALE, y as usize / SCALE)];
+            *p = match color {
+                ColorIndex::Empty => Rgba([0, 0, 0, 0]),
+                color => palette[color].to_rgba(),
+            };
+        }
+        black_box(&indexed_out);
+    });
+
+    let mut lut_out = vec![0u8; SIZE * SCALE * SIZE * SCALE * 4];
+    bench("lut straight to rgba", || {
+        rle_to_rgba(black_box(&rle), 0, &lut, &mut lut_out, SIZE, SIZE, SCALE);
+        black_box(&lut_out);
+    });
+
+    // both paths have to agree on every pixel
+    assert_eq!(indexed_out.as_raw(), &lut_out);
+}
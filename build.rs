@@ -1,3 +1,4 @@
+#[cfg(windows)]
 use std::process::Command;
 
 #[cfg(windows)]
@@ -13,17 +14,19 @@ fn main() {
     res.compile().unwrap();
 }
 
+#[cfg(windows)]
 fn git_semver() -> String {
     let output = Command::new("git")
-        .args(&["describe", "HEAD"])
+        .args(["describe", "HEAD"])
         .output()
         .unwrap();
     String::from_utf8(output.stdout).unwrap().trim().to_string()
 }
 
+#[cfg(windows)]
 fn git_hash() -> String {
     let output = Command::new("git")
-        .args(&["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "--short", "HEAD"])
         .output()
         .unwrap();
     String::from_utf8(output.stdout).unwrap().trim().to_string()
@@ -1,13 +1,15 @@
-use std::ops::{Index, IndexMut, DerefMut};
+use std::{ops::{Index, IndexMut, DerefMut}, path::Path};
 
 use bevy::{prelude::*, window::PrimaryWindow, render::camera::{ScalingMode, Viewport}};
 use bevy_egui::{EguiPlugin, EguiContexts, egui::{self, FontId, FontFamily, Slider, TextEdit}};
+use palette_files::{load_palettes, load_roles, save_palettes, save_roles};
 use image::{init_picture_render, update_pixels, encoder::{EncoderPlugin, RLEncodedString, RLEncodedBytes, RLEncoderSettings, RLEncodedStringSubmission}};
-use sixteenbit_encoding::types::{ColorIndex, PaletteCollection, IndexedImage};
+use sixteenbit_encoding::{encodings::TextEncoding, types::{ColorIndex, ColorRoles, IndexedPixel, PaletteCollection, IndexedImage}};
 use utils::world_to_grid;
 use widgets::{color_index, tool_selector};
 
 mod image;
+mod palette_files;
 mod widgets;
 mod utils;
 
@@ -81,17 +83,14 @@ impl<const N: usize, const W: usize> PixelData<N,W> {
     /// ends and commits the move operation
     pub fn end_move(&mut self, cursor_pos: Vec2) {
         // apply the final transformation based on the final delta
-        match &self.move_op {
-            Some(mv_op) => {
-                let mut delta = cursor_pos - mv_op.move_start;
-                delta.y = -delta.y;
-                let (offset_x,offset_y) = {
-                    let rounded = delta.round();
-                    (rounded.x as i32, rounded.y as i32)
-                };
-                self.pixels.shift(offset_x,offset_y);
-            },
-            _=> {},
+        if let Some(mv_op) = &self.move_op {
+            let mut delta = cursor_pos - mv_op.move_start;
+            delta.y = -delta.y;
+            let (offset_x,offset_y) = {
+                let rounded = delta.round();
+                (rounded.x as i32, rounded.y as i32)
+            };
+            self.pixels.shift(offset_x,offset_y);
         }
         // finally clear the move op so the renderer stops outputting offset pixels
         self.move_op = None;
@@ -102,6 +101,10 @@ impl<const N: usize, const W: usize> PixelData<N,W> {
 #[derive(Resource, Default)]
 pub struct PalettesData(PaletteCollection<u8>);
 
+/// Names and icons of the color indices shown in the color selector
+#[derive(Resource, Default)]
+pub struct RolesData(ColorRoles);
+
 #[derive(Resource, Default)]
 pub struct EditorSettings {
     pub selected_color: ColorIndex,
@@ -143,6 +146,7 @@ fn main() {
     .init_resource::<OccupiedScreenSpace>()
     .init_resource::<CursorWorldCoords>()
     .init_resource::<PalettesData>()
+    .init_resource::<RolesData>()
     .insert_resource(EditorSettings {
         selected_color: ColorIndex::Dark,
         selected_palette: 0,
@@ -186,51 +190,44 @@ fn input_system(
     {return;}
 
     
-    match cursor_type.as_ref() {
-        CursorType::Move => {
-            // start move operation
-            // and store starting position
-            if buttons.just_pressed(MouseButton::Left) {
-                pixels.start_move(cursor.0);
-            } else if buttons.pressed(MouseButton::Left) {
-                // update current pos on the move event
-                pixels.update_move(cursor.0);
-            }
-
-            // end move operation
-            if buttons.just_released(MouseButton::Left) {
-                pixels.end_move(cursor.0)
-            }
+    if let CursorType::Move = cursor_type.as_ref() {
+        // start move operation
+        // and store starting position
+        if buttons.just_pressed(MouseButton::Left) {
+            pixels.start_move(cursor.0);
+        } else if buttons.pressed(MouseButton::Left) {
+            // update current pos on the move event
+            pixels.update_move(cursor.0);
+        }
 
-            // cancel move op with right click
-            if buttons.just_pressed(MouseButton::Right) {
-                pixels.cancel_move();
-            }
+        // end move operation
+        if buttons.just_released(MouseButton::Left) {
+            pixels.end_move(cursor.0)
+        }
 
-        },
-        _ => {},
+        // cancel move op with right click
+        if buttons.just_pressed(MouseButton::Right) {
+            pixels.cancel_move();
+        }
     }
 
     if buttons.pressed(MouseButton::Left) {
-        match world_to_grid(cursor.0) {
-            Some((x,y)) => {
-                match cursor_type.as_ref() {
-                    CursorType::Pencil(p) => {
-                        pixels[(x,y)] = *p;
-                        eprintln!("Placing pixel at: {}/{}", x, y);
-
-                    },
-                    CursorType::Eraser => {
-                        pixels[(x,y)] = ColorIndex::Empty;
-                    },
-                    CursorType::FillBucket(p) => {
-                        // TODO impl fill algo
-                        pixels[(x,y)] = *p;
-                    },
-                    _ => {},
-                }
-            },
-            _=> {}
+        if let Some((x,y)) = world_to_grid(cursor.0) {
+            match cursor_type.as_ref() {
+                CursorType::Pencil(p) => {
+                    pixels[(x,y)] = *p;
+                    eprintln!("Placing pixel at: {}/{}", x, y);
+
+                },
+                CursorType::Eraser => {
+                    pixels[(x,y)] = ColorIndex::Empty;
+                },
+                CursorType::FillBucket(p) => {
+                    // TODO impl fill algo
+                    pixels[(x,y)] = *p;
+                },
+                _ => {},
+            }
         }
     }
 }
@@ -252,6 +249,8 @@ fn update_pen_color(
     }
 }
 
+// bevy systems take everything they touch as arguments
+#[allow(clippy::too_many_arguments)]
 fn ui_controls_system(
     mut contexts: EguiContexts,
     mut occupied_screen_space: ResMut<OccupiedScreenSpace>,
@@ -261,8 +260,10 @@ fn ui_controls_system(
     rle_encoded_string: Res<RLEncodedString>,
     mut rle_encoded_string_submit: ResMut<RLEncodedStringSubmission>,
     rle_encoded_bytes: Res<RLEncodedBytes>,
-    palette: Res<PalettesData>,
+    mut palette: ResMut<PalettesData>,
+    mut roles: ResMut<RolesData>,
     mut input_rle_string: Local<String>,
+    mut palette_path: Local<String>,
 ) {
     let ctx = contexts.ctx_mut();
 
@@ -292,10 +293,24 @@ fn ui_controls_system(
             ui.horizontal(|ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
-    
+                        // TODO open projects
                     }
                     if ui.button("Save").clicked() {
-    
+                        // TODO save projects
+                    }
+                    ui.separator();
+                    // palette file, the format comes from the extension (gpl, pal, hex or txt).
+                    // roles are kept next to it in a .roles file
+                    ui.add(TextEdit::singleline(palette_path.deref_mut()).hint_text("palettes.gpl"));
+                    if ui.button("Open Palettes").clicked() {
+                        if let Some(palettes) = load_palettes(Path::new(palette_path.as_str())) {
+                            palette.0 = palettes;
+                        }
+                        roles.0 = load_roles(Path::new(palette_path.as_str())).unwrap_or_default();
+                    }
+                    if ui.button("Save Palettes").clicked() {
+                        save_palettes(&palette.0, Path::new(palette_path.as_str()));
+                        save_roles(&roles.0, Path::new(palette_path.as_str()));
                     }
                 });
 
@@ -313,63 +328,18 @@ fn ui_controls_system(
                 );
                 ui.separator();
 
-                // color selector
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::Empty,
-                    "❌",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::Dark,
-                    "🎩",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::Bright,
-                    "🌞",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::Skin,
-                    "👨",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::ShirtAccent1,
-                    "👕",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::PantsAccent2,
-                    "👖",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::EyesAccent3,
-                    "👀",
-                    &palette.0[set.selected_palette]
-                );
-                color_index(
-                    ui,
-                    &mut set.selected_color,
-                    ColorIndex::Accent4,
-                    "🎨",
-                    &palette.0[set.selected_palette]
-                );
+                // color selector, one button per role
+                for (index, role) in roles.0.iter() {
+                    if let Some(color) = ColorIndex::from_u8(index) {
+                        color_index(
+                            ui,
+                            &mut set.selected_color,
+                            color,
+                            role,
+                            &palette.0[set.selected_palette]
+                        );
+                    }
+                }
             });
 
             // this must be absolutely last
@@ -394,7 +364,16 @@ fn ui_controls_system(
                         )).labelled_by(vt).changed() {
                             rle_encoder_settings.set_changed();
                         }
-                        if rle_encoded_bytes.0.bytes.len() > 0 {
+                        egui::ComboBox::from_label("Text Encoding")
+                            .selected_text(rle_encoder_settings.text_encoding.to_string())
+                            .show_ui(ui, |ui| {
+                                for encoding in TextEncoding::ALL {
+                                    if ui.selectable_label(rle_encoder_settings.text_encoding == encoding, encoding.to_string()).clicked() {
+                                        rle_encoder_settings.text_encoding = encoding;
+                                    }
+                                }
+                            });
+                        if !rle_encoded_bytes.0.bytes.is_empty() {
                             let header_bits = format!(
                                 "Header bits: {:#b} encoded_width: {} left_offset: {}",
                                 rle_encoded_bytes.0.bytes[0],
@@ -409,7 +388,7 @@ fn ui_controls_system(
                     
                     ui.vertical(|ui| {
                         ui.heading("RLE Decoder");
-                        TextEdit::singleline(input_rle_string.deref_mut()).hint_text("Enter RLE Hex String").show(ui);
+                        TextEdit::singleline(input_rle_string.deref_mut()).hint_text("Enter RLE Hex, Base64url or Base58 String").show(ui);
                     });
 
                     if ui.button("Apply").clicked() {
@@ -423,7 +402,7 @@ fn ui_controls_system(
             }
 
             ui.horizontal(|ui| {
-                ui.label(format!("Hex Encoded ({} Bytes): ", rle_encoded_bytes.0.bytes.len()));
+                ui.label(format!("{} Encoded ({} Bytes): ", rle_encoder_settings.text_encoding, rle_encoded_bytes.0.bytes.len()));
                 if ui.button("📋").on_hover_text("Click to copy").clicked() {
                     ui.output_mut(|out| {
                         out.copied_text = rle_encoded_string.0.clone()
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 use crate::{TOTAL_PIXELS, EDITOR_SIZE, PixelData};
-use sixteenbit_encoding::encodings::{OneByteRle, rle_on_indexed};
+use sixteenbit_encoding::encodings::{OneByteRle, TextEncoding, rle_on_indexed};
 
 pub struct EncoderPlugin;
 
@@ -27,6 +27,8 @@ pub struct RLEncoderSettings {
     /// Useful for drawing in place assets that we know will always have a fixed offset
     /// Ex. Pants
     pub vertical_trim: u8,
+    /// text form the encoding is displayed and copied in
+    pub text_encoding: TextEncoding,
 }
 
 /// The most recently generated encoding of the canvas,
@@ -40,7 +42,7 @@ pub struct RLEncodedBytes(pub OneByteRle);
 pub struct RLEncodedStringSubmission(pub Option<String>);
 
 /// The most recently generated encoding of the canvas,
-/// displayed as a hex string.
+/// displayed as text in the encoding picked in the settings.
 #[derive(Resource, Default)]
 pub struct RLEncodedString(pub String);
 
@@ -60,7 +62,7 @@ pub fn update_encoding(
         let encoder: OneByteRle = (&new_pixels.pixels).into();
 
         encoded_bytes.0 = encoder;
-        encoded_string.0 = hex::encode(&encoded_bytes.0.bytes);
+        encoded_string.0 = encoded_bytes.0.to_text(rle_encoder_settings.text_encoding);
     }
 }
 
@@ -71,10 +73,10 @@ pub fn update_rle_input(
     mut canvas_indexed_pixels: ResMut<PixelData<TOTAL_PIXELS,EDITOR_SIZE>>,
 ) {
     if new_rle.is_changed() {
-        if let Some(hex_str) = &new_rle.0 {
-            match hex::decode(hex_str.trim_matches(' '))
-            .map(OneByteRle::new_with_bytes) {
-                Ok(Some(decoder)) => {
+        if let Some(rle_str) = &new_rle.0 {
+            // hex, base64url and base58 are told apart by their prefix
+            match rle_str.parse::<OneByteRle>() {
+                Ok(decoder) => {
                     // apply the RLE bytes to our indexed canvas
                     rle_on_indexed(
                         &mut canvas_indexed_pixels.pixels,
@@ -86,10 +88,6 @@ pub fn update_rle_input(
                 Err(e) => {
                     error!("RLE Decode Error: {e}");
                 }
-                _=> {
-                    // decode failed
-                    warn!("RLE Decode Failed.");
-                }
             }
         }
     }
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+use bevy::log::{info, warn};
+use sixteenbit_encoding::types::{ColorIndex, ColorRoles, IndexedPixel, PaletteCollection, PaletteFileFormat};
+
+fn format_of(path: &Path) -> Option<PaletteFileFormat> {
+    let format = path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(PaletteFileFormat::from_extension);
+    if format.is_none() {
+        warn!("UNKNOWN PALETTE FILE EXTENSION {}", path.display());
+    }
+    format
+}
+
+/// Reads a palette file into all 8 palettes, the format is picked from the extension
+pub fn load_palettes(path: &Path) -> Option<PaletteCollection<u8>> {
+    let format = format_of(path)?;
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("COULD NOT READ PALETTE FILE {}: {e}", path.display());
+            return None;
+        },
+    };
+    let palettes = PaletteCollection::from_palette_file(&text, format)?;
+    info!("loaded palettes from {}", path.display());
+    Some(palettes)
+}
+
+/// Writes all 8 palettes, the format is picked from the extension and the palette is named after the file.
+/// Returns false (and warns) if it couldn't be written
+pub fn save_palettes(palettes: &PaletteCollection<u8>, path: &Path) -> bool {
+    let Some(format) = format_of(path) else {
+        return false;
+    };
+    let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("Palette");
+    match std::fs::write(path, palettes.to_palette_file(format, name)) {
+        Ok(()) => {
+            info!("saved palettes to {}", path.display());
+            true
+        },
+        Err(e) => {
+            warn!("COULD NOT WRITE PALETTE FILE {}: {e}", path.display());
+            false
+        },
+    }
+}
+
+/// the roles file kept next to a palette file, ex. `palettes.roles` for `palettes.gpl`
+pub fn roles_path(palette_path: &Path) -> PathBuf {
+    palette_path.with_extension("roles")
+}
+
+/// Reads the roles next to a palette file, None if there's no roles file
+/// or it doesn't name every color index
+pub fn load_roles(palette_path: &Path) -> Option<ColorRoles> {
+    let path = roles_path(palette_path);
+    if !path.exists() {
+        return None;
+    }
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("COULD NOT READ ROLES FILE {}: {e}", path.display());
+            return None;
+        },
+    };
+    let roles = ColorRoles::from_roles_file(&text)?;
+    // the color selector draws one button per role, so every color needs one
+    if roles.iter().count() <= ColorIndex::COLORS {
+        warn!("ROLES FILE {} NAMES {} ROLES, NEEDS {}", path.display(), roles.iter().count(), ColorIndex::COLORS + 1);
+        return None;
+    }
+    info!("loaded roles from {}", path.display());
+    Some(roles)
+}
+
+/// Writes the roles next to a palette file.
+/// Returns false (and warns) if it couldn't be written
+pub fn save_roles(roles: &ColorRoles, palette_path: &Path) -> bool {
+    let path = roles_path(palette_path);
+    match std::fs::write(&path, roles.to_roles_file()) {
+        Ok(()) => {
+            info!("saved roles to {}", path.display());
+            true
+        },
+        Err(e) => {
+            warn!("COULD NOT WRITE ROLES FILE {}: {e}", path.display());
+            false
+        },
+    }
+}
@@ -22,8 +22,8 @@ pub fn world_to_grid(world_pos: Vec2) -> Option<(usize,usize)> {
     // return in bounds result or None
     if x < 0. || x >= EDITOR_SIZE as f32
     || y < 0. || y >= EDITOR_SIZE as f32 {
-        return None;
+        None
     } else {
-        return Some((x as usize, y as usize))
+        Some((x as usize, y as usize))
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use bevy_egui::egui::{Ui, RichText, Color32};
-use sixteenbit_encoding::types::{ColorIndex, Palette};
+use sixteenbit_encoding::types::{ColorIndex, ColorRole, Palette};
 
 use crate::CursorType;
 
@@ -7,20 +7,20 @@ pub fn color_index(
     ui: &mut Ui,
     selected_color: &mut ColorIndex,
     alt: ColorIndex,
-    text: &str,
+    role: &ColorRole,
     palette: &Palette<u8>
 ) {
     let color = if alt == ColorIndex::Empty {
         Color32::from_rgb(90, 75, 75)
     } else {
         let col = palette[alt];
-        Color32::from_rgb(col.0[0], col.0[1], col.0[2])
+        Color32::from_rgba_unmultiplied(col.0[0], col.0[1], col.0[2], col.0[3])
     };
     ui.radio_value(
         selected_color,
         alt,
-        RichText::new(text).color(color)
-    );
+        RichText::new(&role.icon).color(color)
+    ).on_hover_text(&role.name);
 }
 
 pub fn selector_button(
@@ -0,0 +1,207 @@
+use bevy::log::info;
+use crate::types::{ColorIndex, IndexedImage};
+use super::{indexed_to_rle_with_format, OneByteRle, RleFormat, ScanOrder};
+
+/// A pixel that was recolored to make an encoding fit its budget
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PixelChange {
+    pub x: u8,
+    pub y: u8,
+    pub from: ColorIndex,
+    pub to: ColorIndex,
+}
+
+/// Result of [`indexed_to_rle_budget`]
+#[derive(Clone)]
+pub struct BudgetRle<const N: usize, const W: usize> {
+    pub rle: OneByteRle,
+    /// the image the encoding was made from, with every change applied
+    pub image: IndexedImage<N, W>,
+    /// changed pixels, in the order they were changed
+    pub changes: Vec<PixelChange>,
+    /// false when every change that still saved bytes was made and the encoding is still over budget
+    pub fits: bool,
+}
+
+/// Encodes the image with [`indexed_to_rle_with_format`], giving up pixels until it takes at most `max_bytes`.
+///
+/// Only single pixel runs are touched (ex. isolated speckles and one pixel holes).
+/// Each one is recolored to match a neighbour along its line, so the runs on both sides can merge.
+/// On every step the change that is least visible, counted as how many of the 4 neighbours
+/// already share the pixel's color, and saves bytes is made, ties going to the change that saves the most.
+/// Images already within budget come back unchanged.
+pub fn indexed_to_rle_budget<const N: usize, const W: usize>(image: &IndexedImage<N, W>, max_bytes: usize, format: RleFormat) -> BudgetRle<N, W> {
+    let mut image = image.clone();
+    let mut rle = indexed_to_rle_with_format(&image, format);
+    let mut changes = vec![];
+
+    while rle.bytes.len() > max_bytes {
+        // (visibility, encoding, change) of the best change so far
+        let mut best: Option<(usize, OneByteRle, PixelChange)> = None;
+
+        for change in single_pixel_runs(&image, format.scan) {
+            let (x, y) = (change.x as usize, change.y as usize);
+            image[(x, y)] = change.to;
+            let candidate = indexed_to_rle_with_format(&image, format);
+            image[(x, y)] = change.from;
+
+            // changes that don't save anything are never worth it
+            if candidate.bytes.len() >= rle.bytes.len() {
+                continue;
+            }
+            let visibility = same_neighbours(&image, x, y);
+            let better = match &best {
+                Some((v, b, _)) => (visibility, candidate.bytes.len()) < (*v, b.bytes.len()),
+                None => true,
+            };
+            if better {
+                best = Some((visibility, candidate, change));
+            }
+        }
+
+        match best {
+            Some((_, candidate, change)) => {
+                image[(change.x as usize, change.y as usize)] = change.to;
+                rle = candidate;
+                changes.push(change);
+            },
+            None => break,
+        }
+    }
+
+    info!("budget encoding to {} bytes changed {} pixels", rle.bytes.len(), changes.len());
+
+    BudgetRle {
+        fits: rle.bytes.len() <= max_bytes,
+        rle,
+        image,
+        changes,
+    }
+}
+
+/// Finds every pixel that is a run of its own along its line,
+/// along with the color it would merge into
+fn single_pixel_runs<const N: usize, const W: usize>(image: &IndexedImage<N, W>, scan: ScanOrder) -> Vec<PixelChange> {
+    let height = N / W;
+    let mut out = vec![];
+    for (x, y, p) in image.enumerate_pixels() {
+        let (x, y) = (x as usize, y as usize);
+
+        // the pixels before and after this one in scan order
+        let (before, after) = match scan {
+            ScanOrder::ColumnMajor => (
+                y.checked_sub(1).map(|y| image[(x, y)]),
+                (y + 1 < height).then(|| image[(x, y + 1)]),
+            ),
+            _ => (
+                x.checked_sub(1).map(|x| image[(x, y)]),
+                (x + 1 < W).then(|| image[(x + 1, y)]),
+            ),
+        };
+        if before == Some(*p) || after == Some(*p) {
+            continue;
+        }
+
+        // a pixel between two runs of the same color joins them, otherwise it merges into the side it blends with more
+        let to = match (before, after) {
+            (Some(b), Some(a)) if a == b => b,
+            (Some(b), Some(a)) => if color_neighbours(image, x, y, a) > color_neighbours(image, x, y, b) { a } else { b },
+            (Some(b), None) => b,
+            (None, Some(a)) => a,
+            (None, None) => continue,
+        };
+        out.push(PixelChange { x: x as u8, y: y as u8, from: *p, to });
+    }
+    out
+}
+
+/// how many of the 4 neighbours have the given color
+fn color_neighbours<const N: usize, const W: usize>(image: &IndexedImage<N, W>, x: usize, y: usize, color: ColorIndex) -> usize {
+    let height = N / W;
+    [
+        x.checked_sub(1).map(|x| (x, y)),
+        (x + 1 < W).then_some((x + 1, y)),
+        y.checked_sub(1).map(|y| (x, y)),
+        (y + 1 < height).then_some((x, y + 1)),
+    ]
+        .into_iter()
+        .flatten()
+        .filter(|position| image[*position] == color)
+        .count()
+}
+
+/// how many of the 4 neighbours share the pixel's color
+fn same_neighbours<const N: usize, const W: usize>(image: &IndexedImage<N, W>, x: usize, y: usize) -> usize {
+    color_neighbours(image, x, y, image[(x, y)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::sample;
+    use crate::encodings::{indexed_to_rle, rle_to_indexed};
+    use crate::types::IndexedPixel;
+
+    /// the sample character with 8 random speckles added, like in the readme
+    fn speckled() -> IndexedImage<256, 16> {
+        // xorshift, so the speckles are the same every run
+        let mut state: u64 = 5;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut image = sample();
+        for _ in 0..8 {
+            let (x, y) = ((next() % 16) as usize, (next() % 16) as usize);
+            image[(x, y)] = ColorIndex::from_u8((next() % 8) as u8).unwrap();
+        }
+        image
+    }
+
+    fn changed_pixels(a: &IndexedImage<256, 16>, b: &IndexedImage<256, 16>) -> usize {
+        a.enumerate_pixels().filter(|(x, y, p)| **p != b[(*x as usize, *y as usize)]).count()
+    }
+
+    #[test]
+    fn readme_budget_sizes() {
+        let image = speckled();
+        assert_eq!(indexed_to_rle(&image).bytes.len(), 60);
+
+        let budget = indexed_to_rle_budget(&image, 53, RleFormat::default());
+        assert!(budget.fits);
+        assert_eq!(budget.changes.len(), 4);
+
+        let budget = indexed_to_rle_budget(&image, 45, RleFormat::default());
+        assert!(budget.fits);
+        assert!(budget.rle.bytes.len() <= 45);
+        assert_eq!(budget.changes.len(), 11);
+    }
+
+    #[test]
+    fn changes_match_the_encoding() {
+        let image = speckled();
+        for max_bytes in [60, 53, 45, 30] {
+            let budget = indexed_to_rle_budget(&image, max_bytes, RleFormat::default());
+            assert_eq!(rle_to_indexed::<256, 16>(&budget.rle, 0), budget.image);
+            assert_eq!(changed_pixels(&image, &budget.image), budget.changes.len());
+        }
+    }
+
+    #[test]
+    fn within_budget_is_unchanged() {
+        let budget = indexed_to_rle_budget(&speckled(), 60, RleFormat::default());
+        assert!(budget.fits);
+        assert!(budget.changes.is_empty());
+        assert_eq!(budget.image, speckled());
+    }
+
+    #[test]
+    fn impossible_budget_does_not_fit() {
+        let budget = indexed_to_rle_budget(&speckled(), 10, RleFormat::default());
+        assert!(!budget.fits);
+        assert!(budget.rle.bytes.len() > 10);
+        assert_eq!(changed_pixels(&speckled(), &budget.image), budget.changes.len());
+    }
+}
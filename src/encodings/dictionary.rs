@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use super::{split_format, CollectionSizes, OneByteRle};
+
+// references set the top bit, leaving 7 bits for the entry index
+const MAX_ENTRIES: usize = 0x1 << 7;
+const REFERENCE_FLAG: u8 = 0x1 << 7;
+// literal groups hold up to 127 run bytes after their count byte
+const LITERAL_LIMIT: usize = (0x1 << 7) - 1;
+// longest sequence of run bytes a single entry can hold
+const MAX_ENTRY_LENGTH: usize = 16;
+
+/// Run byte sequences shared by the assets of a collection (ex. the outlines all heads have in common).
+/// Assets are coded as references into the dictionary plus literal run bytes,
+/// and the dictionary is stored once for the whole collection.
+///
+/// Coded asset layout: raw format prefix (if any) and header byte, then items of either
+/// - `1iii_iiii`: the dictionary entry at index `i`
+/// - `0nnn_nnnn` followed by `n` literal run bytes
+///
+/// Entries are sequences of run bytes only and never include a header or format prefix,
+/// so assets written with any [`super::RleFormat`] extension can share a dictionary.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunDictionary {
+    entries: Vec<Vec<u8>>,
+}
+
+impl RunDictionary {
+    /// Greedily picks the run byte sequences that save the most bytes across the collection,
+    /// keeping at most `max_entries` of them (capped at 128)
+    pub fn build<'a>(assets: impl IntoIterator<Item = &'a OneByteRle>, max_entries: usize) -> Self {
+        // parts of the streams not covered by an entry yet
+        let mut segments: Vec<Vec<u8>> = assets.into_iter()
+            .map(|asset| asset.run_bytes().to_vec())
+            .collect();
+        let mut entries: Vec<Vec<u8>> = vec![];
+
+        while entries.len() < usize::min(max_entries, MAX_ENTRIES) {
+            // count every sequence, sorted so ties always resolve the same way
+            let mut counts: BTreeMap<&[u8], usize> = BTreeMap::new();
+            for segment in &segments {
+                for length in 2..=usize::min(MAX_ENTRY_LENGTH, segment.len()) {
+                    for window in segment.windows(length) {
+                        *counts.entry(window).or_default() += 1;
+                    }
+                }
+            }
+
+            // each use saves the sequence minus its reference byte,
+            // and the entry itself costs its bytes plus a length byte
+            let best = counts.into_iter()
+                .map(|(sequence, count)| {
+                    let savings = (count * (sequence.len() - 1)) as isize - (sequence.len() + 1) as isize;
+                    (savings, sequence)
+                })
+                .max_by_key(|(savings, _)| *savings);
+
+            let entry = match best {
+                Some((savings, sequence)) if savings > 0 => sequence.to_vec(),
+                _ => break,
+            };
+
+            // later entries can only come from what this one doesn't cover
+            segments = segments.iter()
+                .flat_map(|segment| split_on(segment, &entry))
+                .filter(|segment| segment.len() >= 2)
+                .collect();
+            entries.push(entry);
+        }
+
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[Vec<u8>] {
+        &self.entries
+    }
+
+    /// Layout: entry count, then every entry as a length byte followed by its run bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.entries.len() as u8];
+        for entry in &self.entries {
+            out.push(entry.len() as u8);
+            out.extend_from_slice(entry);
+        }
+        out
+    }
+
+    /// Returns None if the bytes are cut short or hold too many entries
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (count, mut rest) = bytes.split_first()?;
+        if *count as usize > MAX_ENTRIES {
+            return None;
+        }
+        let mut entries = Vec::with_capacity(*count as usize);
+        for _ in 0..*count {
+            let (length, after) = rest.split_first()?;
+            entries.push(after.get(..*length as usize)?.to_vec());
+            rest = &after[*length as usize..];
+        }
+        Some(Self { entries })
+    }
+
+    /// Codes the asset with the fewest bytes possible for this dictionary.
+    /// Returns None for an encoding without a header
+    pub fn encode(&self, rle: &OneByteRle) -> Option<Vec<u8>> {
+        rle.get_header()?;
+        let runs = rle.run_bytes();
+
+        // cheapest cost to code everything up to each position, and the step that got there
+        let mut cost = vec![usize::MAX; runs.len() + 1];
+        let mut step: Vec<Item> = vec![Item::Literal(0); runs.len() + 1];
+        cost[0] = 0;
+        for i in 0..runs.len() {
+            if cost[i] == usize::MAX {
+                continue;
+            }
+            for (index, entry) in self.entries.iter().enumerate() {
+                let end = i + entry.len();
+                if runs[i..].starts_with(entry) && cost[i] + 1 < cost[end] {
+                    cost[end] = cost[i] + 1;
+                    step[end] = Item::Reference(index);
+                }
+            }
+            for length in 1..=usize::min(LITERAL_LIMIT, runs.len() - i) {
+                let end = i + length;
+                if cost[i] + 1 + length < cost[end] {
+                    cost[end] = cost[i] + 1 + length;
+                    step[end] = Item::Literal(length);
+                }
+            }
+        }
+
+        // walk back from the end to find the items that were picked
+        let mut items = vec![];
+        let mut position = runs.len();
+        while position > 0 {
+            let item = step[position];
+            position -= match item {
+                Item::Reference(index) => self.entries[index].len(),
+                Item::Literal(length) => length,
+            };
+            items.push((position, item));
+        }
+
+        let mut out = rle.header_bytes().to_vec();
+        for (position, item) in items.into_iter().rev() {
+            match item {
+                Item::Reference(index) => out.push(REFERENCE_FLAG | index as u8),
+                Item::Literal(length) => {
+                    out.push(length as u8);
+                    out.extend_from_slice(&runs[position..position + length]);
+                },
+            }
+        }
+        Some(out)
+    }
+
+    /// Restores the run bytes, the format is read from the raw prefix.
+    /// Returns None if the coded bytes reference a missing entry or are cut short
+    pub fn decode(&self, bytes: &[u8]) -> Option<OneByteRle> {
+        let (_, stream) = split_format(bytes)?;
+        let (_, mut rest) = stream.split_first()?;
+        let mut out = bytes[..bytes.len() - rest.len()].to_vec();
+        while let Some((item, after)) = rest.split_first() {
+            if item & REFERENCE_FLAG != 0 {
+                out.extend_from_slice(self.entries.get((item & !REFERENCE_FLAG) as usize)?);
+                rest = after;
+            } else {
+                let length = *item as usize;
+                out.extend_from_slice(after.get(..length)?);
+                rest = &after[length..];
+            }
+        }
+        OneByteRle::new_with_bytes(out)
+    }
+}
+
+/// one step of a coded asset
+#[derive(Debug, Copy, Clone)]
+enum Item {
+    Reference(usize),
+    Literal(usize),
+}
+
+/// Splits the bytes around every non overlapping occurrence of the pattern
+fn split_on(bytes: &[u8], pattern: &[u8]) -> Vec<Vec<u8>> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i + pattern.len() <= bytes.len() {
+        if bytes[i..].starts_with(pattern) {
+            parts.push(bytes[start..i].to_vec());
+            i += pattern.len();
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    parts.push(bytes[start..].to_vec());
+    parts
+}
+
+/// Dictionary codes every asset, next to the plain size of the collection.
+/// The shared size is the dictionary written with [`RunDictionary::to_bytes`]
+pub fn compare_dictionary_sizes<'a>(dictionary: &RunDictionary, assets: impl IntoIterator<Item = &'a OneByteRle>) -> CollectionSizes {
+    let mut sizes = CollectionSizes {
+        shared: dictionary.to_bytes().len(),
+        ..Default::default()
+    };
+    for asset in assets {
+        sizes.plain += asset.bytes.len();
+        sizes.coded += dictionary.encode(asset).map_or(0, |coded| coded.len());
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::{sample, variations};
+    use crate::encodings::{indexed_to_rle_with_format, RleFormat};
+
+    #[test]
+    fn dictionary_round_trips() {
+        let dictionary = RunDictionary::build(variations().iter(), 128);
+        assert_eq!(RunDictionary::from_bytes(&dictionary.to_bytes()), Some(dictionary));
+    }
+
+    #[test]
+    fn assets_round_trip() {
+        let assets = variations();
+        let dictionary = RunDictionary::build(assets.iter(), 128);
+        for asset in &assets {
+            assert_eq!(dictionary.decode(&dictionary.encode(asset).unwrap()).unwrap().bytes, asset.bytes);
+        }
+        // an empty dictionary codes everything as literals
+        let empty = RunDictionary::default();
+        assert_eq!(empty.decode(&empty.encode(&assets[0]).unwrap()).unwrap().bytes, assets[0].bytes);
+    }
+
+    #[test]
+    fn prefixed_formats_round_trip() {
+        let format = RleFormat { extended_runs: true, control_ops: true, ..Default::default() };
+        let rle = indexed_to_rle_with_format(&sample(), format);
+        let dictionary = RunDictionary::build([&rle], 16);
+        let decoded = dictionary.decode(&dictionary.encode(&rle).unwrap()).unwrap();
+        assert_eq!(decoded.format, format);
+        assert_eq!(decoded.bytes, rle.bytes);
+    }
+
+    #[test]
+    fn readme_sizes() {
+        let assets = variations();
+        let dictionary = RunDictionary::build(assets.iter(), 128);
+        assert_eq!(dictionary.entries().len(), 21);
+        assert_eq!(compare_dictionary_sizes(&dictionary, assets.iter()), CollectionSizes { plain: 972, coded: 730, shared: 84 });
+    }
+
+    #[test]
+    fn malformed_assets_are_rejected() {
+        let dictionary = RunDictionary::build(variations().iter(), 4);
+        // missing entry
+        assert!(dictionary.decode(&[0x48, REFERENCE_FLAG | 100]).is_none());
+        // literal group cut short
+        assert!(dictionary.decode(&[0x48, 3, 0x20, 0x21]).is_none());
+        // no header
+        assert!(dictionary.decode(&[]).is_none());
+    }
+
+    #[test]
+    fn malformed_dictionaries_are_rejected() {
+        assert!(RunDictionary::from_bytes(&[]).is_none());
+        assert!(RunDictionary::from_bytes(&[129]).is_none());
+        // entry shorter than its length byte
+        assert!(RunDictionary::from_bytes(&[1, 4, 0x20, 0x21]).is_none());
+    }
+}
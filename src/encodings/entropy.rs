@@ -0,0 +1,392 @@
+use bevy::log::warn;
+use super::{split_format, OneByteRle};
+
+// code lengths are stored as nibbles in the table
+const MAX_CODE_LENGTH: u8 = 15;
+const COLOR_SYMBOLS: usize = 0x1 << 3;
+const LENGTH_SYMBOLS: usize = 0x1 << 5;
+// the run byte count takes at most 3 bytes (21 bits), far more than any asset holds
+const MAX_COUNT_BYTES: usize = 3;
+
+/// Canonical Huffman code over `S` symbols.
+/// Only the code length of each symbol is stored, the codes themselves are derived from them,
+/// so a table costs 4 bits per symbol. A length of 0 means the symbol has no code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HuffmanCode<const S: usize> {
+    lengths: [u8; S],
+}
+
+impl<const S: usize> HuffmanCode<S> {
+    /// Builds the code from how often each symbol was seen.
+    /// Every symbol gets a code, even unseen ones, so any stream can still be encoded
+    pub fn from_counts(counts: &[u32; S]) -> Self {
+        // +1 so unseen symbols still get a (long) code
+        let mut weights = counts.map(|c| c as u64 + 1);
+        loop {
+            let lengths = huffman_lengths(&weights);
+            if lengths.iter().all(|l| *l <= MAX_CODE_LENGTH) {
+                return Self { lengths };
+            }
+            // flatten the distribution until the deepest code fits in a nibble
+            weights = weights.map(|w| w.div_ceil(2));
+        }
+    }
+
+    /// Returns None if the lengths can't form a prefix code
+    pub fn from_lengths(lengths: [u8; S]) -> Option<Self> {
+        // kraft inequality, in units of the longest possible code
+        let kraft: u32 = lengths.iter()
+            .filter(|l| **l > 0)
+            .map(|l| 1 << (MAX_CODE_LENGTH - *l))
+            .sum();
+        if lengths.iter().any(|l| *l > MAX_CODE_LENGTH) || kraft > 1 << MAX_CODE_LENGTH {
+            return None;
+        }
+        Some(Self { lengths })
+    }
+
+    pub fn lengths(&self) -> &[u8; S] {
+        &self.lengths
+    }
+
+    /// canonical codes: shorter codes first, ties broken by symbol order
+    fn codes(&self) -> [u16; S] {
+        let mut codes = [0; S];
+        let mut code: u16 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            for (symbol, l) in self.lengths.iter().enumerate() {
+                if *l == length {
+                    codes[symbol] = code;
+                    code += 1;
+                }
+            }
+            code <<= 1;
+        }
+        codes
+    }
+
+    fn write(&self, codes: &[u16; S], symbol: usize, out: &mut BitWriter) {
+        out.write(codes[symbol], self.lengths[symbol]);
+    }
+
+    /// Reads one symbol, walking the canonical code one bit at a time
+    fn read(&self, bits: &mut BitReader) -> Option<usize> {
+        // first canonical code of the current length
+        let mut first: u16 = 0;
+        let mut code: u16 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= bits.read()? as u16;
+            let mut index = 0;
+            for (symbol, l) in self.lengths.iter().enumerate() {
+                if *l == length {
+                    if code.wrapping_sub(first) == index {
+                        return Some(symbol);
+                    }
+                    index += 1;
+                }
+            }
+            first = (first + index) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+/// Plain huffman tree building. Returns the depth of every symbol
+fn huffman_lengths<const S: usize>(weights: &[u64; S]) -> [u8; S] {
+    let mut lengths = [0; S];
+    let mut groups: Vec<(u64, Vec<usize>)> = weights.iter()
+        .enumerate()
+        .map(|(symbol, w)| (*w, vec![symbol]))
+        .collect();
+
+    while groups.len() > 1 {
+        // heaviest first, so the two lightest are at the end
+        groups.sort_by_key(|group| std::cmp::Reverse(group.0));
+        let (w1, mut s1) = groups.pop().unwrap_or_default();
+        let (w2, s2) = groups.pop().unwrap_or_default();
+        // every symbol under the new node moves one level deeper
+        for symbol in s1.iter().chain(s2.iter()) {
+            lengths[*symbol] += 1;
+        }
+        s1.extend(s2);
+        groups.push((w1 + w2, s1));
+    }
+
+    lengths
+}
+
+/// Static entropy coding table shared by every asset of a collection.
+/// Run bytes are split into their 3 color bits and 5 length bits,
+/// and each half is coded with its own [`HuffmanCode`].
+/// The table only ever sees run bytes, the format prefix and header are stored raw,
+/// so one table can code assets written with any [`super::RleFormat`] extension.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EntropyTable {
+    pub colors: HuffmanCode<COLOR_SYMBOLS>,
+    pub lengths: HuffmanCode<LENGTH_SYMBOLS>,
+}
+
+impl EntropyTable {
+    /// size of the table once written with [`EntropyTable::to_bytes`]
+    pub const SIZE: usize = (COLOR_SYMBOLS + LENGTH_SYMBOLS) / 2;
+
+    /// Builds the table from the run byte statistics of every asset in the collection
+    pub fn from_collection<'a>(assets: impl IntoIterator<Item = &'a OneByteRle>) -> Self {
+        let mut colors = [0; COLOR_SYMBOLS];
+        let mut lengths = [0; LENGTH_SYMBOLS];
+        for asset in assets {
+            for byte in asset.run_bytes() {
+                colors[(byte >> 5) as usize] += 1;
+                lengths[(byte & 0x1F) as usize] += 1;
+            }
+        }
+        Self {
+            colors: HuffmanCode::from_counts(&colors),
+            lengths: HuffmanCode::from_counts(&lengths),
+        }
+    }
+
+    /// Packs every code length into a nibble, colors first
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        let nibbles = self.colors.lengths.iter().chain(self.lengths.lengths.iter());
+        for (i, length) in nibbles.enumerate() {
+            out[i / 2] |= length << if i.is_multiple_of(2) { 4 } else { 0 };
+        }
+        out
+    }
+
+    /// Returns None if either code in the table is invalid
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let nibble = |i: usize| (bytes[i / 2] >> if i.is_multiple_of(2) { 4 } else { 0 }) & 0xF;
+        Some(Self {
+            colors: HuffmanCode::from_lengths(std::array::from_fn(nibble))?,
+            lengths: HuffmanCode::from_lengths(std::array::from_fn(|i| nibble(i + COLOR_SYMBOLS)))?,
+        })
+    }
+
+    /// Layout: raw format prefix (if any) and header byte, run byte count as LEB128, then the bit packed codes.
+    /// Returns None for an encoding without a header
+    pub fn encode(&self, rle: &OneByteRle) -> Option<Vec<u8>> {
+        rle.get_header()?;
+        let runs = rle.run_bytes();
+        let mut out = rle.header_bytes().to_vec();
+        write_count(runs.len(), &mut out);
+
+        let color_codes = self.colors.codes();
+        let length_codes = self.lengths.codes();
+        let mut bits = BitWriter::new(out);
+        for byte in runs {
+            self.colors.write(&color_codes, (byte >> 5) as usize, &mut bits);
+            self.lengths.write(&length_codes, (byte & 0x1F) as usize, &mut bits);
+        }
+        Some(bits.finish())
+    }
+
+    /// Restores the run bytes, the format is read from the raw prefix.
+    /// Returns None (and warns) if the count is malformed or claims more run bytes than the codes could hold
+    pub fn decode(&self, bytes: &[u8]) -> Option<OneByteRle> {
+        let (_, stream) = split_format(bytes)?;
+        let (_, rest) = stream.split_first()?;
+        let header_bytes = &bytes[..bytes.len() - rest.len()];
+
+        let Some((count, used)) = read_count(rest) else {
+            warn!("ENCOUNTERED INVALID RUN BYTE COUNT WHILE DECODING ENTROPY CODED RLE");
+            return None;
+        };
+        let codes = &rest[used..];
+        // every run byte takes at least one bit for its color and one for its length
+        if count > codes.len() * 8 / 2 {
+            warn!("ENTROPY CODED RLE CLAIMS {count} RUN BYTES BUT ONLY HOLDS {} BYTES OF CODES", codes.len());
+            return None;
+        }
+
+        let mut out = header_bytes.to_vec();
+        let mut bits = BitReader::new(codes);
+        for _ in 0..count {
+            let color = self.colors.read(&mut bits)? as u8;
+            let length = self.lengths.read(&mut bits)? as u8;
+            out.push(color << 5 | length);
+        }
+
+        OneByteRle::new_with_bytes(out)
+    }
+}
+
+/// Writes the count as LEB128, 7 bits at a time
+fn write_count(mut count: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (count & 0x7F) as u8;
+        count >>= 7;
+        if count == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Reads a LEB128 count, returning it along with the bytes it took.
+/// None if it is cut short, longer than 3 bytes, or has trailing zero groups (overlong)
+fn read_count(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut count = 0;
+    for (i, byte) in bytes.iter().enumerate().take(MAX_COUNT_BYTES) {
+        count |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            // a zero group at the end could have been left out
+            if i > 0 && *byte == 0 {
+                return None;
+            }
+            return Some((count, i + 1));
+        }
+    }
+    None
+}
+
+/// Byte counts for a collection stored plain vs coded with a shared table or dictionary,
+/// see [`compare_entropy_sizes`] and [`super::compare_dictionary_sizes`]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct CollectionSizes {
+    /// every asset as plain [`OneByteRle`] bytes
+    pub plain: usize,
+    /// every asset once coded
+    pub coded: usize,
+    /// the shared table or dictionary, paid once per collection
+    pub shared: usize,
+}
+
+/// Entropy codes every asset with `table`, next to the plain size of the collection
+pub fn compare_entropy_sizes<'a>(table: &EntropyTable, assets: impl IntoIterator<Item = &'a OneByteRle>) -> CollectionSizes {
+    let mut sizes = CollectionSizes {
+        shared: EntropyTable::SIZE,
+        ..Default::default()
+    };
+    for asset in assets {
+        sizes.plain += asset.bytes.len();
+        sizes.coded += table.encode(asset).map_or(0, |coded| coded.len());
+    }
+    sizes
+}
+
+/// Writes bits most significant first
+struct BitWriter {
+    bytes: Vec<u8>,
+    used_bits: u8,
+}
+
+impl BitWriter {
+    /// bits get appended after the existing bytes
+    fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, used_bits: 8 }
+    }
+
+    fn write(&mut self, code: u16, length: u8) {
+        for i in (0..length).rev() {
+            if self.used_bits == 8 {
+                self.bytes.push(0);
+                self.used_bits = 0;
+            }
+            let bit = ((code >> i) & 1) as u8;
+            if let Some(last) = self.bytes.last_mut() {
+                *last |= bit << (7 - self.used_bits);
+            }
+            self.used_bits += 1;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Some(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::{sample, sample_rle, variations};
+    use crate::encodings::{indexed_to_rle_with_format, RleFormat};
+
+    #[test]
+    fn table_round_trips() {
+        let table = EntropyTable::from_collection(variations().iter());
+        assert_eq!(EntropyTable::from_bytes(&table.to_bytes()), Some(table));
+    }
+
+    #[test]
+    fn assets_round_trip() {
+        let assets = variations();
+        let table = EntropyTable::from_collection(assets.iter());
+        for asset in &assets {
+            assert_eq!(table.decode(&table.encode(asset).unwrap()).unwrap().bytes, asset.bytes);
+        }
+    }
+
+    #[test]
+    fn prefixed_formats_round_trip() {
+        let format = RleFormat { extended_runs: true, control_ops: true, row_refs: true, ..Default::default() };
+        let rle = indexed_to_rle_with_format(&sample(), format);
+        let table = EntropyTable::from_collection([&rle]);
+        let decoded = table.decode(&table.encode(&rle).unwrap()).unwrap();
+        assert_eq!(decoded.format, format);
+        assert_eq!(decoded.bytes, rle.bytes);
+    }
+
+    #[test]
+    fn readme_sizes() {
+        let rle = sample_rle();
+        let table = EntropyTable::from_collection([&rle]);
+        assert_eq!(compare_entropy_sizes(&table, [&rle]), CollectionSizes { plain: 53, coded: 37, shared: 20 });
+
+        let assets = variations();
+        let table = EntropyTable::from_collection(assets.iter());
+        assert_eq!(compare_entropy_sizes(&table, assets.iter()), CollectionSizes { plain: 972, coded: 671, shared: 20 });
+    }
+
+    #[test]
+    fn truncated_codes_are_rejected() {
+        let rle = sample_rle();
+        let table = EntropyTable::from_collection([&rle]);
+        let coded = table.encode(&rle).unwrap();
+        for length in 0..coded.len() - 1 {
+            assert!(table.decode(&coded[..length]).is_none(), "decoded {length} bytes");
+        }
+    }
+
+    #[test]
+    fn malformed_counts_are_rejected() {
+        let table = EntropyTable::from_collection([&sample_rle()]);
+        // unterminated
+        assert!(table.decode(&[0x48, 0x80, 0x80, 0x80, 0x01, 0xFF]).is_none());
+        assert!(table.decode(&[0x48, 0x80]).is_none());
+        // overlong, 1 written with a trailing zero group
+        assert!(table.decode(&[0x48, 0x81, 0x00, 0xFF]).is_none());
+        // far more run bytes than the codes could hold
+        assert!(table.decode(&[0x48, 0xFF, 0xFF, 0x7F, 0xFF]).is_none());
+    }
+
+    #[test]
+    fn invalid_tables_are_rejected() {
+        // 8 color codes of length 1 can't form a prefix code
+        let mut bytes = [0; EntropyTable::SIZE];
+        bytes[..4].fill(0x11);
+        assert!(EntropyTable::from_bytes(&bytes).is_none());
+    }
+}
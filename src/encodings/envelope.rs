@@ -0,0 +1,310 @@
+use bevy::log::warn;
+use crate::types::IndexedImage;
+use super::{rle_to_indexed, EntropyTable, OneByteRle, RunDictionary};
+
+/// First two bytes of every envelope.
+/// A bare stream can't start with them: a header of `0xFF` places the image at offset 7 with width 32,
+/// which doesn't fit any canvas up to 32 pixels wide
+pub const ENVELOPE_MAGIC: [u8; 2] = [0xFF, 0x16];
+/// Newest envelope layout. Envelopes with a newer version are rejected
+pub const ENVELOPE_VERSION: u8 = 1;
+// magic, version, encoding, width, height, trim, payload length (u16 little endian)
+const HEADER_SIZE: usize = 9;
+const CRC_SIZE: usize = 4;
+
+/// Which encoding the payload of an envelope holds
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EncodingId {
+    /// plain [`OneByteRle`] bytes, format prefix and header included
+    OneByteRle = 0,
+    /// [`OneByteRle`] bytes coded with an [`EntropyTable`]
+    Entropy = 1,
+    /// [`OneByteRle`] bytes coded with a [`RunDictionary`]
+    Dictionary = 2,
+}
+
+impl EncodingId {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(EncodingId::OneByteRle),
+            1 => Some(EncodingId::Entropy),
+            2 => Some(EncodingId::Dictionary),
+            _ => None,
+        }
+    }
+}
+
+/// Self describing container around an encoded asset.
+///
+/// Layout: magic `0xFF 0x16`, version, encoding id,
+/// canvas width, canvas height, vertical trim, payload length (u16 little endian),
+/// the payload, then a CRC-32 of everything before it (little endian).
+/// The [`super::RleFormat`] is part of the payload, in the format prefix of the stream.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Envelope<'a> {
+    pub version: u8,
+    pub encoding: EncodingId,
+    /// size of the canvas the asset was made for
+    pub width: u8,
+    pub height: u8,
+    pub vertical_trim: u8,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Wraps a plain encoding
+    pub fn from_rle(rle: &'a OneByteRle, width: u8, height: u8, vertical_trim: u8) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            encoding: EncodingId::OneByteRle,
+            width,
+            height,
+            vertical_trim,
+            payload: &rle.bytes,
+        }
+    }
+
+    /// Writes the envelope out with its checksum.
+    /// Returns None (and warns) if the payload is too long for its u16 length
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let Ok(payload_length) = u16::try_from(self.payload.len()) else {
+            warn!("ENVELOPE PAYLOAD OF {} BYTES IS OVER THE {} BYTE LIMIT", self.payload.len(), u16::MAX);
+            return None;
+        };
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.payload.len() + CRC_SIZE);
+        out.extend_from_slice(&ENVELOPE_MAGIC);
+        out.push(self.version);
+        out.push(self.encoding as u8);
+        out.push(self.width);
+        out.push(self.height);
+        out.push(self.vertical_trim);
+        out.extend_from_slice(&payload_length.to_le_bytes());
+        out.extend_from_slice(self.payload);
+        out.extend_from_slice(&crc32(&out).to_le_bytes());
+        Some(out)
+    }
+
+    /// Reads an envelope, checking the checksum before anything else.
+    /// Returns None if the bytes are not an envelope, are corrupt or come from a newer version
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        if !bytes.starts_with(&ENVELOPE_MAGIC) || bytes.len() < HEADER_SIZE + CRC_SIZE {
+            return None;
+        }
+        let (body, crc) = bytes.split_at(bytes.len() - CRC_SIZE);
+        if crc32(body).to_le_bytes() != crc {
+            warn!("ENVELOPE CHECKSUM DOES NOT MATCH, ASSET IS CORRUPT");
+            return None;
+        }
+
+        let version = body[2];
+        if version > ENVELOPE_VERSION {
+            warn!("ENCOUNTERED ENVELOPE VERSION {version}, NEWEST SUPPORTED IS {ENVELOPE_VERSION}");
+            return None;
+        }
+        let payload_length = u16::from_le_bytes([body[7], body[8]]) as usize;
+        if body.len() != HEADER_SIZE + payload_length {
+            warn!("ENVELOPE PAYLOAD LENGTH DOES NOT MATCH");
+            return None;
+        }
+
+        Some(Self {
+            version,
+            encoding: EncodingId::from_byte(body[3])?,
+            width: body[4],
+            height: body[5],
+            vertical_trim: body[6],
+            payload: &body[HEADER_SIZE..],
+        })
+    }
+
+    /// Decodes the payload back into plain [`OneByteRle`] bytes.
+    /// Entropy and dictionary coded payloads need the table or dictionary of their collection,
+    /// returns None when it is missing
+    pub fn to_rle(&self, table: Option<&EntropyTable>, dictionary: Option<&RunDictionary>) -> Option<OneByteRle> {
+        match self.encoding {
+            EncodingId::OneByteRle => OneByteRle::new_with_bytes(self.payload.to_vec()),
+            EncodingId::Entropy => match table {
+                Some(table) => table.decode(self.payload),
+                None => {
+                    warn!("ENTROPY CODED ASSET NEEDS AN ENTROPY TABLE TO DECODE");
+                    None
+                },
+            },
+            EncodingId::Dictionary => match dictionary {
+                Some(dictionary) => dictionary.decode(self.payload),
+                None => {
+                    warn!("DICTIONARY CODED ASSET NEEDS A RUN DICTIONARY TO DECODE");
+                    None
+                },
+            },
+        }
+    }
+}
+
+/// What [`identify`] found in a blob of bytes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Identified<'a> {
+    /// an envelope with a matching checksum
+    Envelope(Envelope<'a>),
+    /// no envelope. Assumed to be a plain [`OneByteRle`] stream (format prefix included),
+    /// like every asset from before envelopes. There is no checksum to validate these against
+    Bare(&'a [u8]),
+}
+
+impl<'a> Identified<'a> {
+    /// Decodes into plain [`OneByteRle`] bytes, along with the vertical trim to decode them at
+    pub fn to_rle(&self, table: Option<&EntropyTable>, dictionary: Option<&RunDictionary>) -> Option<(OneByteRle, u8)> {
+        match self {
+            Identified::Envelope(envelope) => Some((envelope.to_rle(table, dictionary)?, envelope.vertical_trim)),
+            Identified::Bare(bytes) => Some((OneByteRle::new_with_bytes(bytes.to_vec())?, 0)),
+        }
+    }
+}
+
+/// Detects whether the bytes are an envelope or a bare stream.
+/// Returns None if they start like an envelope but are corrupt, unsupported, or empty
+pub fn identify(bytes: &[u8]) -> Option<Identified<'_>> {
+    if bytes.is_empty() {
+        return None;
+    }
+    if bytes.starts_with(&ENVELOPE_MAGIC) {
+        return Envelope::from_bytes(bytes).map(Identified::Envelope);
+    }
+    Some(Identified::Bare(bytes))
+}
+
+/// Identifies the bytes and decodes them with the right decoder.
+/// Warns if an envelope was made for a different canvas size, but decodes anyway
+pub fn decode_identified<const N: usize, const W: usize>(
+    bytes: &[u8],
+    table: Option<&EntropyTable>,
+    dictionary: Option<&RunDictionary>,
+) -> Option<IndexedImage<N, W>> {
+    let identified = identify(bytes)?;
+    if let Identified::Envelope(envelope) = identified {
+        if envelope.width as usize != W || envelope.height as usize != N / W {
+            warn!("DECODING {}x{} ASSET ONTO A {}x{} CANVAS", envelope.width, envelope.height, W, N / W);
+        }
+    }
+    let (rle, trim) = identified.to_rle(table, dictionary)?;
+    Some(rle_to_indexed(&rle, trim))
+}
+
+/// CRC-32 (IEEE), the same one zip and png use
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            // reflected polynomial
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::{sample, sample_rle, variations, SAMPLE};
+    use crate::encodings::{indexed_to_rle_optimized, Mirror, RleFormat};
+
+    #[test]
+    fn round_trips() {
+        let rle = sample_rle();
+        let bytes = Envelope::from_rle(&rle, 16, 16, 0).to_bytes().unwrap();
+        let envelope = Envelope::from_bytes(&bytes).unwrap();
+        assert_eq!(envelope, Envelope::from_rle(&rle, 16, 16, 0));
+        assert_eq!(envelope.to_rle(None, None).unwrap().bytes, rle.bytes);
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, None), Some(sample()));
+    }
+
+    #[test]
+    fn readme_overhead() {
+        let format = RleFormat { mirror: Mirror::Even, ..Default::default() };
+        let optimized = indexed_to_rle_optimized(&sample(), format).unwrap();
+        assert_eq!(optimized.rle.bytes.len(), 36);
+        let bytes = Envelope::from_rle(&optimized.rle, 16, 16, optimized.vertical_trim).to_bytes().unwrap();
+        assert_eq!(bytes.len(), 49);
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, None), Some(sample()));
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let rle = sample_rle();
+        let mut bytes = Envelope::from_rle(&rle, 16, 16, 0).to_bytes().unwrap();
+        bytes[HEADER_SIZE] ^= 1;
+        assert_eq!(Envelope::from_bytes(&bytes), None);
+        assert_eq!(identify(&bytes), None);
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let rle = sample_rle();
+        let bytes = Envelope::from_rle(&rle, 16, 16, 0).to_bytes().unwrap();
+        for length in [2, HEADER_SIZE, bytes.len() - 1] {
+            assert_eq!(Envelope::from_bytes(&bytes[..length]), None);
+        }
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let rle = sample_rle();
+        let envelope = Envelope { version: ENVELOPE_VERSION + 1, ..Envelope::from_rle(&rle, 16, 16, 0) };
+        assert_eq!(Envelope::from_bytes(&envelope.to_bytes().unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_wrong_payload_length() {
+        let rle = sample_rle();
+        let mut bytes = Envelope::from_rle(&rle, 16, 16, 0).to_bytes().unwrap();
+        bytes.truncate(bytes.len() - CRC_SIZE);
+        bytes[7] += 1;
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        assert_eq!(Envelope::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn coded_payloads_need_their_table() {
+        let assets = variations();
+        let table = EntropyTable::from_collection(assets.iter());
+        let dictionary = RunDictionary::build(assets.iter(), 128);
+        let rle = &assets[0];
+
+        let payload = table.encode(rle).unwrap();
+        let envelope = Envelope { encoding: EncodingId::Entropy, payload: &payload, ..Envelope::from_rle(rle, 16, 16, 0) };
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, None), None);
+        assert_eq!(decode_identified::<256, 16>(&bytes, Some(&table), None), Some(rle_to_indexed(rle, 0)));
+
+        let payload = dictionary.encode(rle).unwrap();
+        let envelope = Envelope { encoding: EncodingId::Dictionary, payload: &payload, ..Envelope::from_rle(rle, 16, 16, 0) };
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, None), None);
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, Some(&dictionary)), Some(rle_to_indexed(rle, 0)));
+    }
+
+    #[test]
+    fn bare_streams() {
+        let bytes = hex::decode(SAMPLE).unwrap();
+        assert_eq!(identify(&bytes), Some(Identified::Bare(&bytes)));
+        assert_eq!(decode_identified::<256, 16>(&bytes, None, None), Some(sample()));
+        assert_eq!(identify(&[]), None);
+    }
+
+    #[test]
+    fn rejects_oversized_payloads() {
+        let rle = sample_rle();
+        let payload = vec![0x20; u16::MAX as usize + 1];
+        let envelope = Envelope { payload: &payload, ..Envelope::from_rle(&rle, 16, 16, 0) };
+        assert_eq!(envelope.to_bytes(), None);
+        let envelope = Envelope { payload: &payload[1..], ..envelope };
+        assert_eq!(envelope.to_bytes().map(|bytes| bytes.len()), Some(u16::MAX as usize + 13));
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
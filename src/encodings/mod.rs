@@ -1,3 +1,26 @@
+mod budget;
+mod dictionary;
+mod entropy;
+mod envelope;
 mod onebyte_rle;
+mod palette;
+mod rgba;
+mod serialize;
+mod stream;
+mod text;
+mod wide_rle;
 
-pub use onebyte_rle::*;
\ No newline at end of file
+pub use budget::*;
+pub use dictionary::*;
+pub use entropy::*;
+pub use envelope::*;
+pub use onebyte_rle::*;
+pub use palette::*;
+pub use rgba::*;
+pub use stream::*;
+pub use text::*;
+pub use wide_rle::*;
+
+// sample assets for tests across the crate
+#[cfg(test)]
+pub(crate) use onebyte_rle::tests as fixtures;
\ No newline at end of file
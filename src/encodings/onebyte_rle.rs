@@ -1,12 +1,178 @@
 use bevy::log::{info, warn};
-use crate::types::{ColorIndex, IndexedImage};
-use bytemuck::Contiguous;
+use crate::types::{ColorIndex, IndexedImage, IndexedPixel};
+use super::{RleDecoder, SkipEmpty};
 
 // run length must be within 5 bits, so less than 0x20
-const RUN_LENGTH_LIMIT: u8 = 0x1 << 5;
+const RUN_LENGTH_LIMIT: u8 = RunByte::<ColorIndex>::RUN_LENGTH_LIMIT;
 const OFFSET_LIMIT: u8 = 0x1 << 3;
 const WIDTH_MASK: u8 = (0x1 << 5)-1;
-const RUN_LENGTH_MASK: u8 = (0x1 << 5)-1;
+// a continuation byte can extend a run by up to 32 * 32 pixels
+const CONTINUATION_LIMIT: u16 = RUN_LENGTH_LIMIT as u16 * RUN_LENGTH_LIMIT as u16;
+// with control opcodes, empty runs only get the lower 4 bits of length
+const EMPTY_RUN_LIMIT: u8 = 0x1 << 4;
+// first control opcode byte. Opcodes take the place of empty runs longer than 16
+const CONTROL_OP_START: u8 = EMPTY_RUN_LIMIT;
+// end of row opcodes can skip up to 7 extra rows (0x10 to 0x17)
+const SKIP_ROWS_LIMIT: u8 = 7;
+// repeat previous row opcodes (0x18 to 0x1B) copy it 1 to 4 times
+const REPEAT_ROW_OP: u8 = 0x18;
+const REPEAT_ROW_LIMIT: u8 = 4;
+// copy row opcode (0x1C), followed by a byte of how many rows back to copy from, minus one
+const COPY_ROW_OP: u8 = 0x1C;
+/// how far back a copy row opcode can reach. Also the number of rows a decoder has to remember
+pub const ROW_REF_LIMIT: usize = 32;
+/// First byte of every stream written with a non classic [`RleFormat`], followed by the format byte
+/// (see [`RleFormat::to_byte`]) and then the usual header.
+/// As a header it would be offset 7 with width 31, which doesn't fit any canvas up to 32 pixels wide,
+/// so decoders that only know the classic format refuse these streams instead of misdrawing them
+pub const FORMAT_MARKER: u8 = 0xFE;
+
+/// Optional extensions to the classic one byte per run stream.
+/// The default is the classic format that every existing asset is encoded with,
+/// and any extension keeps classic streams decoding exactly as before.
+/// Streams using any extension start with [`FORMAT_MARKER`] and the format byte,
+/// so the format always travels with the bytes.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RleFormat {
+    /// Allows continuation bytes for runs longer than 32 pixels.
+    /// A run byte that repeats the color of the run right before it,
+    /// when that run was shorter than 32, is never written by the classic encoder
+    /// (the two would have been merged). That pattern is reserved as a continuation:
+    /// its length is multiplied by 32 and added onto the previous run.
+    pub extended_runs: bool,
+    /// Reserves the empty run bytes `0x10..=0x1F` (empty runs longer than 16) as control opcodes.
+    /// Empty runs are capped at 16 pixels in exchange, and don't take continuation bytes.
+    /// - `0x10..=0x17` end of row: fills the rest of the row with Empty, then skips 0 to 7 more empty rows
+    /// - `0x18..=0x1F` reserved, decoding stops when one is found (unless `row_refs` is set)
+    ///
+    /// Classic run bytes can take any value, so the opcodes themselves look like valid runs.
+    /// The [`FORMAT_MARKER`] in front of the stream is what keeps classic decoders from reading them.
+    pub control_ops: bool,
+    /// Adds row back reference opcodes on top of `control_ops`, and turns those on even when `control_ops` isn't set.
+    /// Both can only be used at the start of a row.
+    /// - `0x18..=0x1B` copy the previous row 1 to 4 times
+    /// - `0x1C` followed by a row count byte `n`: copy the row `n + 1` rows above, up to 32 back
+    /// - `0x1D..=0x1F` still reserved
+    pub row_refs: bool,
+    /// Rows only hold the left half of a horizontally symmetric image,
+    /// and get mirrored onto the right half when decoding.
+    /// When encoding, anything other than [`Mirror::None`] lets the encoder mirror the image
+    /// if it is symmetric (picking the parity from the image) and falls back to full rows if not.
+    pub mirror: Mirror,
+    /// Order the pixels of the window are written in. Only row major scans can be mirrored
+    pub scan: ScanOrder,
+}
+
+/// How decoded rows are mirrored onto the right half of the image
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Mirror {
+    #[default]
+    None,
+    /// the full image is twice the encoded width
+    Even,
+    /// the last encoded column is the centre column, and is not repeated.
+    /// The full image is twice the encoded width minus one
+    OddCenter,
+}
+
+impl Mirror {
+    /// width of the decoded image for an encoded width
+    pub fn full_width(&self, encoded_width: usize) -> usize {
+        match self {
+            Mirror::None => encoded_width,
+            Mirror::Even => encoded_width * 2,
+            Mirror::OddCenter => encoded_width * 2 - 1,
+        }
+    }
+}
+
+/// Order pixels are visited in when encoding and decoding.
+/// Control opcodes and row references work on lines in this order,
+/// so with column major scans they act on columns instead of rows.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ScanOrder {
+    /// left to right, then top to bottom
+    #[default]
+    RowMajor,
+    /// top to bottom, then left to right.
+    /// The width in the header holds the column height instead (minus one like the width),
+    /// and the offset is still the left offset
+    ColumnMajor,
+    /// like row major, except every odd row runs right to left,
+    /// so a run touching the right edge carries on into the next row
+    Serpentine,
+}
+
+impl ScanOrder {
+    pub const ALL: [ScanOrder; 3] = [ScanOrder::RowMajor, ScanOrder::ColumnMajor, ScanOrder::Serpentine];
+}
+
+impl RleFormat {
+    /// Longest run a single run byte can hold for this color
+    pub fn run_length_limit(&self, color: ColorIndex) -> u8 {
+        if self.has_control_ops() && color == ColorIndex::Empty {
+            EMPTY_RUN_LIMIT
+        } else {
+            RUN_LENGTH_LIMIT
+        }
+    }
+
+    /// whether runs of this color can be followed by a continuation byte
+    fn extends(&self, color: ColorIndex) -> bool {
+        self.extended_runs && !(self.has_control_ops() && color == ColorIndex::Empty)
+    }
+
+    /// row references are opcodes too, so they bring the rest of the control opcodes with them
+    pub fn has_control_ops(&self) -> bool {
+        self.control_ops || self.row_refs
+    }
+
+    /// Packs the format into the byte written after [`FORMAT_MARKER`].
+    /// Layout: `0ssm_mrce` (e extended runs, c control opcodes, r row references, m mirror, s scan order)
+    pub fn to_byte(&self) -> u8 {
+        let mirror = match self.mirror {
+            Mirror::None => 0,
+            Mirror::Even => 1,
+            Mirror::OddCenter => 2,
+        };
+        let scan = match self.scan {
+            ScanOrder::RowMajor => 0,
+            ScanOrder::ColumnMajor => 1,
+            ScanOrder::Serpentine => 2,
+        };
+        self.extended_runs as u8
+            | (self.has_control_ops() as u8) << 1
+            | (self.row_refs as u8) << 2
+            | mirror << 3
+            | scan << 5
+    }
+
+    /// Returns None for bits no format uses
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        let mirror = match (byte >> 3) & 0b11 {
+            0 => Mirror::None,
+            1 => Mirror::Even,
+            2 => Mirror::OddCenter,
+            _ => return None,
+        };
+        let scan = match (byte >> 5) & 0b11 {
+            0 => ScanOrder::RowMajor,
+            1 => ScanOrder::ColumnMajor,
+            2 => ScanOrder::Serpentine,
+            _ => return None,
+        };
+        if byte & 0x80 != 0 {
+            return None;
+        }
+        Some(Self {
+            extended_runs: byte & 0b1 != 0,
+            control_ops: byte & 0b10 != 0,
+            row_refs: byte & 0b100 != 0,
+            mirror,
+            scan,
+        })
+    }
+}
 
 
 /// Structure representing an image encoded with my Domain Specific 1Byte-per-run Color RLE encoding
@@ -14,7 +180,10 @@ const RUN_LENGTH_MASK: u8 = (0x1 << 5)-1;
 pub struct OneByteRle {
     pub header_offset: u8,
     pub header_width: u8,
+    /// format prefix (only for non classic formats), header and run bytes
     pub bytes: Vec<u8>,
+    /// extensions the run bytes were written with. Read from the format prefix of the bytes
+    pub format: RleFormat,
 }
 
 pub trait Rle {
@@ -28,17 +197,24 @@ impl OneByteRle {
             header_offset: 0,
             header_width: 0,
             bytes: vec![],
+            format: RleFormat::default(),
         }
     }
 
-    /// Consumes a vec of bytes to create the encoder decoder object
-    /// returns None if bytes are empty
-    pub fn new_with_bytes(bytes: Vec<u8>) -> Option<Self> {
-        if bytes.len() == 0 {
-            return None;
+    /// Creates an empty encoding that writes runs using the given format extensions
+    pub fn with_format(format: RleFormat) -> Self {
+        Self {
+            format,
+            ..Self::new()
         }
+    }
 
-        let header_byte = bytes[0];
+    /// Consumes a vec of bytes to create the encoder decoder object,
+    /// reading the format from the prefix if there is one.
+    /// returns None if bytes are empty, or the format prefix is cut short or unknown
+    pub fn new_with_bytes(bytes: Vec<u8>) -> Option<Self> {
+        let (format, stream) = split_format(&bytes)?;
+        let header_byte = *stream.first()?;
 
         let (header_offset, header_width) = Self::get_header_from_byte(header_byte);
 
@@ -46,12 +222,13 @@ impl OneByteRle {
             header_offset,
             header_width,
             bytes,
+            format,
         })
     }
 
     pub fn get_header_from_byte(header_byte: u8) -> (u8,u8) {
         // get header from Most Significant 5 bits
-        let header_offset = header_byte.clone() >> 5;
+        let header_offset = header_byte >> 5;
         // get width from Least Significant 3 bits
         let header_width = header_byte & WIDTH_MASK;
 
@@ -61,10 +238,7 @@ impl OneByteRle {
     }
 
     pub fn get_header(&self) -> Option<(u8,u8)> {
-        if self.bytes.len() == 0 {
-            return None;
-        }
-        let header_byte = self.bytes[0];
+        let header_byte = *self.bytes.get(self.runs_start() - 1)?;
 
         info!("GETTING HEADER BYTE {header_byte:#0b}");
 
@@ -76,16 +250,36 @@ impl OneByteRle {
         self.bytes
     }
 
-    /// first byte of the encoding. Sets row width and left offset
+    /// the format prefix (if any) and the header byte
+    pub fn header_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::min(self.runs_start(), self.bytes.len())]
+    }
+
+    /// everything after the header byte
+    pub fn run_bytes(&self) -> &[u8] {
+        self.bytes.get(self.runs_start()..).unwrap_or(&[])
+    }
+
+    fn runs_start(&self) -> usize {
+        match self.bytes.first() {
+            Some(&FORMAT_MARKER) => 3,
+            _ => 1,
+        }
+    }
+
+    /// first byte of the encoding, after the format prefix. Sets row width and left offset
     /// does not encode full canvas width. Decoder will make assumptions about width
     /// For my purposes, the assumed width is 32
     pub fn push_header(&mut self, offset: u8, encode_width: u8) {
         // assert that the header is the first byte being entered
         debug_assert_eq!(self.bytes.len(), 0);
+        if self.format != RleFormat::default() {
+            self.bytes.extend([FORMAT_MARKER, self.format.to_byte()]);
+        }
         self.header_offset = offset;
         self.header_width = encode_width;
         // create header byte
-        let header_byte: u8 = (offset as u8) << 5 | (encode_width & WIDTH_MASK);
+        let header_byte: u8 = offset << 5 | (encode_width & WIDTH_MASK);
         // push the header to the first byte of our array
         self.bytes.push(header_byte);
     }
@@ -94,7 +288,7 @@ impl OneByteRle {
     pub fn push_pixel_run(&mut self, pixel_run: &RunByte) {
         // assert that the header is the first byte being entered
         // by now there should be more than one byte
-        debug_assert!(self.bytes.len() > 0);
+        debug_assert!(!self.bytes.is_empty());
         self.bytes.push(pixel_run.get());
     }
 
@@ -104,6 +298,181 @@ impl OneByteRle {
             self.push_pixel_run(pixel)
         }
     }
+
+    /// Push a run of any length, splitting it into as many run bytes as our format needs.
+    /// Runs pushed back to back should not share a color, or they will be read back as one.
+    pub fn push_run(&mut self, color: ColorIndex, mut length: u16) {
+        let limit = self.format.run_length_limit(color) as u16;
+        while length > 0 {
+            if length <= limit {
+                self.push_pixel_run(&RunByte::new(color, length as u8));
+                break;
+            }
+            let remainder = length % limit;
+            if self.format.extends(color) && remainder != 0 {
+                // a short run followed by a continuation of the same color
+                let extension = u16::min(length - remainder, CONTINUATION_LIMIT);
+                self.push_pixel_run(&RunByte::new(color, remainder as u8));
+                self.push_pixel_run(&RunByte::new(color, (extension / limit) as u8));
+                length -= remainder + extension;
+            } else {
+                // full runs can't be extended, the next byte is always read as a new run
+                self.push_pixel_run(&RunByte::new(color, limit as u8));
+                length -= limit;
+            }
+        }
+    }
+
+    /// Push every run of the image in order, right after the header.
+    /// With control opcodes enabled, empty runs that reach the end of a row
+    /// are written with end of row opcodes whenever that takes fewer bytes.
+    pub fn push_runs(&mut self, runs: &[(ColorIndex, u16)]) {
+        let row_width = self.header_width as usize + 1;
+        // pixels written so far, used to find the column each run starts on
+        let mut position = 0;
+        for &(color, length) in runs {
+            if self.format.has_control_ops() && color == ColorIndex::Empty {
+                self.push_empty_run(position % row_width, row_width, length);
+            } else {
+                self.push_run(color, length);
+            }
+            position += length as usize;
+        }
+    }
+
+    /// Push one end of row opcode, skipping `skip_rows` more empty rows after the current one
+    pub fn push_end_of_row(&mut self, skip_rows: u8) {
+        debug_assert!(self.format.has_control_ops());
+        debug_assert!(skip_rows <= SKIP_ROWS_LIMIT);
+        debug_assert!(!self.bytes.is_empty());
+        self.bytes.push(CONTROL_OP_START + skip_rows);
+    }
+
+    /// Push opcodes copying the previous row `count` times
+    pub fn push_repeat_rows(&mut self, mut count: usize) {
+        debug_assert!(self.format.row_refs);
+        while count > 0 {
+            let repeats = usize::min(count, REPEAT_ROW_LIMIT as usize);
+            self.bytes.push(REPEAT_ROW_OP + repeats as u8 - 1);
+            count -= repeats;
+        }
+    }
+
+    /// Push an opcode copying the row `back` rows above (1 is the previous row)
+    pub fn push_copy_row(&mut self, back: usize) {
+        debug_assert!(self.format.row_refs);
+        debug_assert!(back > 0 && back <= ROW_REF_LIMIT);
+        self.bytes.push(COPY_ROW_OP);
+        self.bytes.push(back as u8 - 1);
+    }
+
+    /// Push every row of the window, replacing rows with references to earlier ones
+    /// wherever the opcodes take fewer bytes than the runs would
+    fn push_rows_with_refs(&mut self, rows: &[Vec<ColorIndex>]) {
+        // empty rows at the bottom are dropped like any trailing empty pixels
+        let used_rows = rows.iter()
+            .rposition(|row| row.iter().any(|p| *p != ColorIndex::Empty))
+            .map_or(0, |last| last + 1);
+        let rows = &rows[..used_rows];
+
+        // pixels of the rows since the last reference
+        let mut literal: Vec<ColorIndex> = vec![];
+        let mut r = 0;
+        while r < rows.len() {
+            // rows in a row that are the same as the one above
+            let repeats = match r.checked_sub(1) {
+                Some(above) => rows[r..].iter().take_while(|row| **row == rows[above]).count(),
+                None => 0,
+            };
+            if repeats > 0 && repeats.div_ceil(REPEAT_ROW_LIMIT as usize) < self.rows_cost(&rows[r..r + repeats]) {
+                self.push_runs(&pixels_to_runs(literal.iter()));
+                literal.clear();
+                self.push_repeat_rows(repeats);
+                r += repeats;
+                continue;
+            }
+
+            // the same row somewhere further up
+            let back = (2..=usize::min(r, ROW_REF_LIMIT)).find(|back| rows[r - back] == rows[r]);
+            if let Some(back) = back.filter(|_| 2 < self.rows_cost(&rows[r..=r])) {
+                self.push_runs(&pixels_to_runs(literal.iter()));
+                literal.clear();
+                self.push_copy_row(back);
+                r += 1;
+                continue;
+            }
+
+            literal.extend(rows[r].iter());
+            r += 1;
+        }
+
+        let mut runs = pixels_to_runs(literal.iter());
+        trim_trailing_empty(&mut runs);
+        self.push_runs(&runs);
+    }
+
+    /// Bytes it takes to write these rows as runs on their own
+    fn rows_cost(&self, rows: &[Vec<ColorIndex>]) -> usize {
+        let mut scratch = Self::with_format(self.format);
+        scratch.push_header(0, self.header_width);
+        scratch.push_runs(&pixels_to_runs(rows.iter().flatten()));
+        scratch.run_bytes().len()
+    }
+
+    /// Writes an empty run starting at `column` either as plain runs
+    /// or as end of row opcodes plus the empty pixels leading into the next row
+    fn push_empty_run(&mut self, column: usize, row_width: usize, length: u16) {
+        let length = length as usize;
+        let empty_limit = EMPTY_RUN_LIMIT as usize;
+        let skip_limit = SKIP_ROWS_LIMIT as usize;
+        // an end of row at the start of a row fills that whole row
+        let row_rest = row_width - column;
+
+        if length >= row_rest {
+            let after_row = length - row_rest;
+            let mut rows = after_row / row_width;
+            let leading = after_row % row_width;
+
+            // the first opcode ends the current row,
+            // every other one starts at a row start and covers 1 + 7 rows
+            let opcodes = 1 + rows.saturating_sub(skip_limit).div_ceil(skip_limit + 1);
+            if opcodes + leading.div_ceil(empty_limit) < length.div_ceil(empty_limit) {
+                let skip = usize::min(rows, skip_limit);
+                self.push_end_of_row(skip as u8);
+                rows -= skip;
+                while rows > 0 {
+                    let skip = usize::min(rows - 1, skip_limit);
+                    self.push_end_of_row(skip as u8);
+                    rows -= skip + 1;
+                }
+                self.push_run(ColorIndex::Empty, leading as u16);
+                return;
+            }
+        }
+
+        self.push_run(ColorIndex::Empty, length as u16);
+    }
+
+    /// Iterates the decoded runs following the header byte
+    pub fn tokens(&self) -> RleTokens<'_> {
+        RleTokens::new(self.run_bytes(), self.format)
+    }
+}
+
+/// Splits the format prefix off a stream, giving the format and the bytes from the header on.
+/// Streams without a prefix are classic.
+/// Returns None (and warns) if the prefix is cut short or holds a format byte no format uses
+pub fn split_format(bytes: &[u8]) -> Option<(RleFormat, &[u8])> {
+    let [FORMAT_MARKER, rest @ ..] = bytes else {
+        return Some((RleFormat::default(), bytes));
+    };
+    match rest.split_first().and_then(|(byte, stream)| Some((RleFormat::from_byte(*byte)?, stream))) {
+        Some(split) => Some(split),
+        None => {
+            warn!("ENCOUNTERED INVALID RLE FORMAT PREFIX");
+            None
+        },
+    }
 }
 
 /// From implemented for reference so that we don't needlessly clone every pixel before encoding
@@ -114,45 +483,146 @@ impl<const N: usize, const W: usize> From<&IndexedImage<N,W>> for OneByteRle {
 }
 
 /// Creates an indexed image buffer from self RLE Bytes
-impl<const N: usize, const W: usize> Into<IndexedImage<N,W>> for OneByteRle {
-    fn into(self) -> IndexedImage<N,W> {
-        rle_to_indexed::<N,W>(&self, 0)
+impl<const N: usize, const W: usize> From<OneByteRle> for IndexedImage<N,W> {
+    fn from(rle: OneByteRle) -> Self {
+        rle_to_indexed::<N,W>(&rle, 0)
     }
 }
 
+/// A single run: the color index in the top `P::BITS` bits and the run length - 1 in the rest.
+/// 3 bit colors (the default) leave 5 bits of length, 1 bit masks leave 7 and 4 bit colors leave 4
 #[derive(Debug,Clone)]
-pub struct RunByte {
-    color: ColorIndex,// first 3 bits from MSB
-    run_length: u8, // last 5 bits closest to LSB
+pub struct RunByte<P: IndexedPixel = ColorIndex> {
+    color: P,// first BITS bits from MSB
+    run_length: u8, // the rest, closest to LSB
 }
 
-impl RunByte {
-    pub fn new(color: ColorIndex, run_length: u8) -> Self {
-        debug_assert!((color as u8) < (0x1 << 3));
-        debug_assert!(run_length < 0x1 << 5);
+impl<P: IndexedPixel> RunByte<P> {
+    /// bits left over for the run length
+    const LENGTH_BITS: u8 = 8 - P::BITS;
+    /// longest run a single byte can hold
+    pub const RUN_LENGTH_LIMIT: u8 = 0x1 << Self::LENGTH_BITS;
+
+    pub fn new(color: P, run_length: u8) -> Self {
+        debug_assert!(run_length > 0 && run_length <= Self::RUN_LENGTH_LIMIT);
         Self {
             color,
             run_length,
         }
     }
+
     pub fn get(&self) -> u8 {
-        // return color bits as first 3
-        // and RLE as last 5 mased with the lower 5 bits
-        (self.color as u8) << 5 | (self.run_length-1 & 0x1F)
+        // return color bits first
+        // and RLE as the rest, masked with the lower length bits
+        self.color.to_u8() << Self::LENGTH_BITS | ((self.run_length - 1) & (Self::RUN_LENGTH_LIMIT - 1))
     }
 
     pub fn from_byte(byte: u8) -> Option<Self> {
-        if let Some(color) = ColorIndex::from_integer(byte.clone() >> 5) {
-            Some(Self {
-                color,
-                run_length: (byte.clone() & RUN_LENGTH_MASK) + 1,
-            })
-        } else {
-            None
+        Some(Self {
+            color: P::from_u8(byte >> Self::LENGTH_BITS)?,
+            run_length: (byte & (Self::RUN_LENGTH_LIMIT - 1)) + 1,
+        })
+    }
+
+    pub fn color(&self) -> P {
+        self.color
+    }
+
+    pub fn run_length(&self) -> u8 {
+        self.run_length
+    }
+}
+
+/// One decoded unit of the run byte stream
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RleToken {
+    /// `length` pixels of `color`. Continuation bytes are already folded into the length
+    Run { color: ColorIndex, length: u16 },
+    /// Fill the rest of the row with Empty (the whole row when at the start of one)
+    /// and then `skip_rows` more rows. Only found with [`RleFormat::control_ops`]
+    EndOfRow { skip_rows: u8 },
+    /// Copy the row `back` rows above `count` times, one row after another.
+    /// Only found at the start of a row with [`RleFormat::row_refs`]
+    CopyRows { back: u8, count: u8 },
+}
+
+/// Reads [`RleToken`]s from run bytes (everything after the header byte)
+pub struct RleTokens<'a> {
+    bytes: std::iter::Peekable<std::slice::Iter<'a, u8>>,
+    format: RleFormat,
+}
+
+impl<'a> RleTokens<'a> {
+    pub fn new(run_bytes: &'a [u8], format: RleFormat) -> Self {
+        Self {
+            bytes: run_bytes.iter().peekable(),
+            format,
         }
     }
 }
 
+impl<'a> Iterator for RleTokens<'a> {
+    type Item = RleToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = *self.bytes.next()?;
+        let run = match RunByte::from_byte(byte) {
+            Some(run) => run,
+            None => {
+                // stop decoding if we hit an invalid byte
+                warn!("ENCOUNTERED INVALID BYTE WHILE DECODING RLE");
+                self.bytes = [].iter().peekable();
+                return None;
+            }
+        };
+
+        let limit = self.format.run_length_limit(run.color);
+
+        // empty runs past the limit are control opcodes
+        if run.run_length > limit {
+            let op = byte - CONTROL_OP_START;
+            if op <= SKIP_ROWS_LIMIT {
+                return Some(RleToken::EndOfRow { skip_rows: op });
+            }
+            if self.format.row_refs {
+                if (REPEAT_ROW_OP..REPEAT_ROW_OP + REPEAT_ROW_LIMIT).contains(&byte) {
+                    return Some(RleToken::CopyRows { back: 1, count: byte - REPEAT_ROW_OP + 1 });
+                }
+                if byte == COPY_ROW_OP {
+                    match self.bytes.next() {
+                        Some(back) if (*back as usize) < ROW_REF_LIMIT => {
+                            return Some(RleToken::CopyRows { back: back + 1, count: 1 });
+                        },
+                        _ => {
+                            warn!("ENCOUNTERED INVALID ROW REFERENCE WHILE DECODING RLE");
+                            self.bytes = [].iter().peekable();
+                            return None;
+                        },
+                    }
+                }
+            }
+            warn!("ENCOUNTERED RESERVED OPCODE {byte:#04x} WHILE DECODING RLE");
+            self.bytes = [].iter().peekable();
+            return None;
+        }
+
+        let mut length = run.run_length as u16;
+
+        // fold in a continuation byte if this run left room for one
+        if self.format.extends(run.color) && run.run_length < limit {
+            if let Some(extension) = self.bytes.peek()
+                .and_then(|b| RunByte::<ColorIndex>::from_byte(**b))
+                .filter(|b| b.color == run.color)
+            {
+                self.bytes.next();
+                length += extension.run_length as u16 * RUN_LENGTH_LIMIT as u16;
+            }
+        }
+
+        Some(RleToken::Run { color: run.color, length })
+    }
+}
+
 /// Receives an array of RLE encoded bytes and outputs them into the IndexedImage format.
 /// Steps:
 /// From y = trim and x = offset, output pixels from left to right
@@ -166,56 +636,14 @@ pub fn rle_to_indexed<const PIXELS: usize, const WIDTH: usize>(rle: &OneByteRle,
 /// takes in RLE Bytes and a reference to an indexed image,
 /// then writes on top of that image with the decoded RLE Pixels
 pub fn rle_on_indexed<'a, const PIXELS: usize, const WIDTH: usize>(image_out: &'a mut IndexedImage<PIXELS, WIDTH>, rle: &'a OneByteRle, trim: u8, overwrite: bool) {
-    match rle.get_header() {
-        Some((header_offset, header_width)) => {
-
-            info!("decoding with header offset {header_offset} width {header_width}");
-
-            let mut encoded_bytes_iter = rle.bytes.iter();
-            // skip header
-            encoded_bytes_iter.next();
-
-            let mut x = header_offset as usize;
-            let mut y = trim as usize;
-
-            let mut pixel_out_count: usize = 0;
-
-            for b in encoded_bytes_iter.map(|b| RunByte::from_byte(*b)) {
-                // stop decoding if we hit an invalid byte
-                let b = if let Some(inner_b) = b {
-                    inner_b
-                } else {
-                    warn!("ENCOUNTERED INVALID BYTE WHILE DECODING RLE");
-                    break;
-                };
-
-                info!("performing run of {}px color {:?}", b.run_length,b.color);
-
-                for _ in 0..b.run_length {
-                    // check for safety that the pixel is in range of our array
-                    let index = 
-                        WIDTH
-                        * y as usize
-                        + x as usize;
-                    // stop decoding if we hit the end of our pixel array
-                    if index >= PIXELS-1 {
-                        break;
-                    }
-                    // output color to pixel coordinate
-                    if overwrite || b.color != ColorIndex::Empty {
-                        image_out[(x,y)] = b.color;
-                    }
-                    // now advance our x and y for the next pixel
-                    pixel_out_count+=1;
-
-                    let real_width = header_width as usize + 1;
-
-                    x = header_offset as usize + (pixel_out_count % real_width);
-                    // advance y when we reach width
-                    if pixel_out_count % real_width == 0 {
-                        y+=1;
-                    }
-                }
+    match RleDecoder::from_rle(rle, trim) {
+        Some(decoder) => {
+            info!("decoding with header offset {} width {}", rle.header_offset, rle.header_width);
+            if overwrite {
+                decoder.decode_into(image_out);
+            } else {
+                // leave the canvas alone where the decoded image is empty
+                decoder.decode_into(&mut SkipEmpty(image_out));
             }
         },
         _=> {
@@ -233,41 +661,42 @@ pub fn rle_on_indexed<'a, const PIXELS: usize, const WIDTH: usize>(image_out: &'
 /// Then for each byte count repeats, wrapping at width
 /// finally, prune trailing Empty/null pixels
 pub fn indexed_to_rle<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>) -> OneByteRle {
-    // these start as oposite from eachother,
-    // then get walked to the right value in the for loop
-    let mut min_x = WIDTH as u8-1;
-    let mut max_x = 0;
+    indexed_to_rle_with_format(image, RleFormat::default())
+}
 
+/// Same as [`indexed_to_rle`] but writes the runs using the given format extensions
+pub fn indexed_to_rle_with_format<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>, format: RleFormat) -> OneByteRle {
     // minimum y to start reading from.
     // values above this are discarded and the image is treated as if it starts from that line
     let min_y = image.vertical_trim;
 
-    for (x,y,p) in image.enumerate_pixels() {
-        // skip the vertical trimmed values
-        if y < min_y { continue; }
-        // replace min with current lowest x
-        match *p {
-            ColorIndex::Empty => {},
-            _ => {
-                if x < min_x {
-                    min_x = x;
-                }
-                // replace max with current largest x
-                if x > max_x {
-                    max_x = x;
-                }
-            }
-        }
-    }
-
     // if canvas was empty we set min and max to 0
-    if min_x > max_x {
-        min_x = 0;
-        max_x = 0;
-    }
+    let (min_x, max_x) = content_columns(image, min_y).unwrap_or_default();
 
     // now we know our offset value as min_x. Cap it at max 3 bits
     let offset = u8::min(min_x, OFFSET_LIMIT-1);
+
+    // columns only have to reach down to the last row holding a pixel
+    if format.scan == ScanOrder::ColumnMajor {
+        let last_row = image.enumerate_pixels()
+            .filter(|(_, _, p)| **p != ColorIndex::Empty)
+            .map(|(_, y, _)| y)
+            .max()
+            .unwrap_or_default();
+        let encode_height = last_row.saturating_sub(min_y);
+        // same limit as the width, the header field is shared
+        debug_assert!(encode_height < 0x1 << 5);
+        return indexed_to_rle_with_placement(image, min_y, offset, encode_height, RleFormat { mirror: Mirror::None, ..format });
+    }
+
+    // only the left half is needed for symmetric images
+    if format.mirror != Mirror::None && format.scan == ScanOrder::RowMajor {
+        if let Some((offset, half_width, mirror)) = mirrored_window(image, min_y, offset) {
+            return indexed_to_rle_with_placement(image, min_y, offset, half_width, RleFormat { mirror, ..format });
+        }
+    }
+    let format = RleFormat { mirror: Mirror::None, ..format };
+
     // assert that offset value is within 3 bits (max value of 7)
     debug_assert!(offset < 0x1 << 3);
     // and encoded width
@@ -279,101 +708,595 @@ pub fn indexed_to_rle<const PIXELS: usize, const WIDTH: usize>(image: &IndexedIm
 
     info!("actual width: {} encoded_width: {}",encode_width + 1,encode_width);
 
-    // run lengths acumulator
-    let mut runs = vec![];
-    // the pixel type of the previous cell
-    // let mut last_pixel = None;
-    for (x,y,p) in image.enumerate_pixels() {
+    indexed_to_rle_with_placement(image, min_y, offset, encode_width, format)
+}
+
+/// Encodes the window of the image starting at row `trim` and column `offset`
+/// that is `encode_width + 1` pixels wide.
+/// With [`ScanOrder::ColumnMajor`] the window is `encode_width + 1` pixels tall instead,
+/// and reaches the right edge of the canvas.
+/// Pixels outside of the window are discarded, so the caller is responsible
+/// for picking a window that covers everything that should be kept.
+pub fn indexed_to_rle_with_placement<const PIXELS: usize, const WIDTH: usize>(
+    image: &IndexedImage<PIXELS, WIDTH>,
+    trim: u8,
+    offset: u8,
+    encode_width: u8,
+    format: RleFormat,
+) -> OneByteRle {
+    debug_assert!(offset < OFFSET_LIMIT);
+    debug_assert!(encode_width < 0x1 << 5);
 
-        // skip pixels before the start of offset
-        if x < offset { continue; }
-        // skip pixels after encode_width + offset
-        if x > encode_width + offset { continue; }
+    // the pixels inside the window, one vec per line in scan order
+    let mut rows: Vec<Vec<ColorIndex>> = vec![];
+    if format.scan == ScanOrder::ColumnMajor {
+        let height = PIXELS / WIDTH;
+        for x in offset as usize..WIDTH {
+            // rows below the canvas read as empty
+            rows.push((trim as usize..=trim as usize + encode_width as usize)
+                .map(|y| if y < height { image[(x, y)] } else { ColorIndex::Empty })
+                .collect());
+        }
+    } else {
+        for (x,y,p) in image.enumerate_pixels() {
 
-        // skip the vertical trimmed values
-        if y < min_y { continue; }
+            // skip pixels before the start of offset
+            if x < offset { continue; }
+            // skip pixels after encode_width + offset
+            if x > encode_width + offset { continue; }
+
+            // skip the vertical trimmed values
+            if y < trim { continue; }
+
+            if x == offset {
+                rows.push(vec![]);
+            }
+            if let Some(row) = rows.last_mut() {
+                row.push(*p);
+            }
+        }
 
-        // for first pixel, simply input it into the acu
-        if runs.len() == 0 {
-            let rb = RunByte::new(
-                *p,
-                1,
-            );
-            // info!("First run byte: {rb:?}");
-            runs.push(rb);
-            continue;
+        if format.scan == ScanOrder::Serpentine {
+            for row in rows.iter_mut().skip(1).step_by(2) {
+                row.reverse();
+            }
         }
+    }
 
-        // now the rest of the pixels
+    // convert to final bytes
+
+    let mut out_bytes = OneByteRle::with_format(format);
+
+    // push header byte (width and offset)
+    out_bytes.push_header(offset, encode_width);
 
+    // push all the run lengths and convert them to bytes
+    let mut runs = pixels_to_runs(rows.iter().flatten());
+    trim_trailing_empty(&mut runs);
+    out_bytes.push_runs(&runs);
+
+    if format.row_refs {
+        // references only pay off for some images, so keep whichever is smaller
+        let mut with_refs = OneByteRle::with_format(format);
+        with_refs.push_header(offset, encode_width);
+        with_refs.push_rows_with_refs(&rows);
+        if with_refs.bytes.len() < out_bytes.bytes.len() {
+            return with_refs;
+        }
+    }
+
+    out_bytes
+}
+
+/// Groups pixels into runs. Runs are not capped here,
+/// they get split into bytes by the format when pushed
+fn pixels_to_runs<'a>(pixels: impl Iterator<Item = &'a ColorIndex>) -> Vec<(ColorIndex, u16)> {
+    // run lengths acumulator
+    let mut runs: Vec<(ColorIndex, u16)> = vec![];
+    for p in pixels {
         // compare last pixel with current one
         // then either push a new run or increment the last
         match runs.last_mut() {
-            Some(last_p) => {
-                if last_p.color == *p && last_p.run_length < RUN_LENGTH_LIMIT {
-                    // same as last byte
-                    // increase the run
-                    last_p.run_length+=1;
-                } else {
-                    // new pixel, push a new run
-                    runs.push(RunByte::new(
-                        *p,
-                        1,
-                    ));
-                }
+            Some((color, length)) if *color == *p => {
+                // same as last pixel
+                // increase the run
+                *length+=1;
             },
-            // should never be None since we always push a first pixel
-            None => unreachable!(),
-        }
-
-        // calculate flat index
-        // this won't be needed if I change how my iterator impl to be a flat 1D array
-        // let index = 
-        //     WIDTH
-        //     * y as usize
-        //     + x as usize;
-        
-        
-        // if index >= PIXELS-1 {
-
-        // }
-    }
-
-    // trunicate trailing null bytes when we reach the last pixel
-    // loops until it runs out of colors to check or it hits a non empty color
-    loop {
-        match runs.last() {
-            Some(RunByte {
-                color,
-                ..
-            }) => {
-                if *color == ColorIndex::Empty {
-                    runs.pop();
-                } else {
-                    // last run is not an empty,
-                    // so stop trunicating 
-                    break;
-                }
+            _ => {
+                // new pixel (or the very first one), push a new run
+                runs.push((*p, 1));
             },
-            None => {
-                // stop trunicating if we run out of items
-                break;
+        }
+    }
+    runs
+}
+
+/// trunicate trailing null bytes when we reach the last pixel
+/// loops until it runs out of colors to check or it hits a non empty color
+fn trim_trailing_empty(runs: &mut Vec<(ColorIndex, u16)>) {
+    while let Some((ColorIndex::Empty, _)) = runs.last() {
+        runs.pop();
+    }
+}
+
+/// Smallest encoding found by [`indexed_to_rle_optimized`],
+/// along with the vertical trim it was encoded with.
+/// The left offset and width are stored in the header byte,
+/// but the trim must be handed to the decoder separately.
+#[derive(Clone)]
+pub struct OptimizedRle {
+    pub rle: OneByteRle,
+    pub vertical_trim: u8,
+}
+
+/// Encodes the image with every scan order, vertical trim, left offset and width the format allows
+/// and keeps the one with the fewest bytes. The scan order of the given format is ignored,
+/// the one that was picked is set in the format of the result.
+/// Mirrored placements are tried too when the format allows mirroring.
+/// Unlike [`indexed_to_rle`] the image's own `vertical_trim` is ignored,
+/// any trim that does not cut off a non-empty pixel is tried instead.
+/// When the content starts further right than the maximum offset of 7,
+/// the window is shifted left and the extra empty columns are encoded as part of the rows.
+/// Returns None (and warns) when the content doesn't fit in any window a header can describe,
+/// which only happens on canvases wider or taller than 32 pixels
+pub fn indexed_to_rle_optimized<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>, format: RleFormat) -> Option<OptimizedRle> {
+    let mut best: Option<OptimizedRle> = None;
+    for scan in ScanOrder::ALL {
+        let Some(candidate) = optimize_placement(image, RleFormat { scan, ..format }) else { continue };
+        // ties go to the earlier scan order, so row major wins when nothing is gained
+        if best.as_ref().is_none_or(|b| candidate.rle.bytes.len() < b.rle.bytes.len()) {
+            best = Some(candidate);
+        }
+    }
+
+    match &best {
+        Some(best) => info!("optimized encoding to {} bytes", best.rle.bytes.len()),
+        None => warn!("IMAGE CONTENT DOES NOT FIT IN ANY RLE HEADER WINDOW"),
+    }
+    best
+}
+
+/// Placement search behind [`indexed_to_rle_optimized`], for the scan order of the format.
+/// Returns None when the content is wider (or taller for columns) than a header can describe
+fn optimize_placement<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>, format: RleFormat) -> Option<OptimizedRle> {
+    let height = (PIXELS / WIDTH) as u8;
+
+    // the first row holding a non-empty pixel. Trimming past it would lose pixels
+    let first_row = image.enumerate_pixels()
+        .find(|(_, _, p)| **p != ColorIndex::Empty)
+        .map(|(_, y, _)| y);
+    // and the last one, which columns have to reach
+    let last_row = image.enumerate_pixels()
+        .filter(|(_, _, p)| **p != ColorIndex::Empty)
+        .map(|(_, y, _)| y)
+        .max()
+        .unwrap_or_default();
+
+    let plain_format = RleFormat { mirror: Mirror::None, ..format };
+
+    let (first_row, (min_x, max_x)) = match (first_row, content_columns(image, 0)) {
+        (Some(first_row), Some(columns)) => (first_row, columns),
+        // nothing to place, so any header will do
+        _ => {
+            return Some(OptimizedRle {
+                rle: indexed_to_rle_with_placement(image, 0, 0, 0, plain_format),
+                vertical_trim: 0,
+            });
+        }
+    };
+
+    let mut best: Option<OptimizedRle> = None;
+    let mut keep_smallest = |rle: OneByteRle, trim: u8| {
+        if best.as_ref().is_none_or(|b| rle.bytes.len() < b.rle.bytes.len()) {
+            best = Some(OptimizedRle {
+                rle,
+                vertical_trim: trim,
+            });
+        }
+    };
+
+    for trim in 0..=u8::min(first_row, height.saturating_sub(1)) {
+        for offset in 0..=u8::min(min_x, OFFSET_LIMIT-1) {
+            // columns reach the right edge, so only their height is picked
+            if format.scan == ScanOrder::ColumnMajor {
+                let max_height = u8::min(RUN_LENGTH_LIMIT, height - trim);
+                for encode_height in (last_row - trim)..max_height {
+                    keep_smallest(indexed_to_rle_with_placement(image, trim, offset, encode_height, plain_format), trim);
+                }
+                continue;
+            }
+
+            // the window must reach the right most pixel without running off the canvas
+            let max_width = u8::min(RUN_LENGTH_LIMIT, (WIDTH as u8).saturating_sub(offset));
+            for encode_width in (max_x - offset)..max_width {
+                keep_smallest(indexed_to_rle_with_placement(image, trim, offset, encode_width, plain_format), trim);
+            }
+
+            // a mirrored window has to stay centred on the content, so the offset decides its width
+            if format.mirror != Mirror::None && format.scan == ScanOrder::RowMajor {
+                if let Some((offset, half_width, mirror)) = mirrored_window(image, trim, offset) {
+                    let mirror_format = RleFormat { mirror, ..format };
+                    keep_smallest(indexed_to_rle_with_placement(image, trim, offset, half_width, mirror_format), trim);
+                }
             }
         }
     }
 
-    // convert to final bytes
+    best
+}
 
-    let mut out_bytes = OneByteRle::new();
+/// Finds the window for a mirrored encoding starting at `offset`, for the rows from `trim` down.
+/// Returns the offset, the encoded width of the left half (minus one like the header) and the parity,
+/// or None when the rows aren't symmetric or the window doesn't fit on the canvas or in a header
+fn mirrored_window<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>, trim: u8, offset: u8) -> Option<(u8, u8, Mirror)> {
+    let (min_x, max_x) = content_columns(image, trim)?;
+    if offset > min_x {
+        return None;
+    }
+    // pad the right side as much as the offset pads the left, so the content stays centred
+    let right = max_x as usize + (min_x - offset) as usize;
+    if right >= WIDTH {
+        return None;
+    }
+    let full_width = right - offset as usize + 1;
+    let half_width = full_width.div_ceil(2);
+    if half_width > RUN_LENGTH_LIMIT as usize {
+        return None;
+    }
 
-    // push header byte (width and offset)
-    out_bytes.push_header(offset, encode_width);
+    let symmetric = image.enumerate_pixels()
+        .filter(|(x, y, _)| *y >= trim && *x >= offset && (*x as usize) < offset as usize + full_width / 2)
+        .all(|(x, y, p)| *p == image[(right + offset as usize - x as usize, y as usize)]);
+    if !symmetric {
+        return None;
+    }
 
-    // push all the run lengths and convert them to bytes
-    out_bytes.append_pixel_runs(&runs);
+    let mirror = if full_width.is_multiple_of(2) { Mirror::Even } else { Mirror::OddCenter };
+    Some((offset, half_width as u8 - 1, mirror))
+}
 
-    out_bytes
+/// Finds the left and right most columns holding a non-empty pixel,
+/// ignoring rows above `trim`. Returns None for an empty canvas.
+fn content_columns<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH>, trim: u8) -> Option<(u8, u8)> {
+    let mut columns: Option<(u8, u8)> = None;
+    for (x,y,p) in image.enumerate_pixels() {
+        // skip the vertical trimmed values
+        if y < trim || *p == ColorIndex::Empty { continue; }
+        columns = Some(match columns {
+            Some((min_x, max_x)) => (u8::min(min_x, x), u8::max(max_x, x)),
+            None => (x, x),
+        });
+    }
+    columns
 }
 
 // impl From<image>
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// the sample character from the readme
+    pub(crate) const SAMPLE: &str = "481220042001216221016041604160016040c060c040600166016122610280628001608660018403a403a002a003a002a002210221";
+
+    pub(crate) fn sample_rle() -> OneByteRle {
+        OneByteRle::new_with_bytes(hex::decode(SAMPLE).unwrap()).unwrap()
+    }
+
+    pub(crate) fn sample() -> IndexedImage<256, 16> {
+        rle_to_indexed(&sample_rle(), 0)
+    }
+
+    /// a few pixels scattered to the far right and bottom of the canvas
+    pub(crate) fn sparse() -> IndexedImage<256, 16> {
+        let mut image = IndexedImage::new();
+        image[(12, 6)] = ColorIndex::Dark;
+        image[(9, 9)] = ColorIndex::Skin;
+        image[(14, 13)] = ColorIndex::Bright;
+        image
+    }
+
+    /// 16 copies of the sample character with 6 pixels changed at random in each,
+    /// like the assets of a collection
+    pub(crate) fn variations() -> Vec<OneByteRle> {
+        // xorshift, so the variations are the same every run
+        let mut state: u64 = 99;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..16).map(|_| {
+            let mut image = sample();
+            for _ in 0..6 {
+                let (x, y) = ((next() % 16) as usize, (next() % 16) as usize);
+                image[(x, y)] = ColorIndex::from_u8((next() % 8) as u8).unwrap();
+            }
+            indexed_to_rle(&image)
+        }).collect()
+    }
+
+    #[test]
+    fn optimized_matches_plain_pixels_and_is_never_larger() {
+        for image in [sample(), sparse(), IndexedImage::new()] {
+            let plain = indexed_to_rle(&image);
+            let optimized = indexed_to_rle_optimized(&image, RleFormat::default()).unwrap();
+            assert!(optimized.rle.bytes.len() <= plain.bytes.len());
+            assert_eq!(rle_to_indexed::<256, 16>(&optimized.rle, optimized.vertical_trim), image);
+        }
+    }
+
+    #[test]
+    fn optimized_trims_empty_rows() {
+        let optimized = indexed_to_rle_optimized(&sparse(), RleFormat::default()).unwrap();
+        assert!(optimized.vertical_trim > 0);
+        assert!(optimized.rle.bytes.len() < indexed_to_rle(&sparse()).bytes.len());
+    }
+
+    #[test]
+    fn run_length_limits_follow_the_color_bits() {
+        assert_eq!(RUN_LENGTH_LIMIT, 32);
+        assert_eq!(RunByte::<bool>::RUN_LENGTH_LIMIT, 128);
+        assert_eq!(RunByte::<crate::types::WideColorIndex>::RUN_LENGTH_LIMIT, 16);
+    }
+
+    const EXTENDED: RleFormat = RleFormat {
+        extended_runs: true,
+        control_ops: false,
+        row_refs: false,
+        mirror: Mirror::None,
+        scan: ScanOrder::RowMajor,
+    };
+
+    #[test]
+    fn extended_runs_use_continuation_bytes() {
+        let mut classic = OneByteRle::new();
+        classic.push_header(0, 31);
+        classic.push_run(ColorIndex::Empty, 80);
+        assert_eq!(classic.bytes[1..], [0x1F, 0x1F, 0x0F]);
+
+        let mut extended = OneByteRle::with_format(EXTENDED);
+        extended.push_header(0, 31);
+        extended.push_run(ColorIndex::Empty, 80);
+        assert_eq!(extended.bytes[extended.bytes.len() - 2..], [0x0F, 0x01]);
+        assert_eq!(extended.tokens().collect::<Vec<_>>(), [RleToken::Run { color: ColorIndex::Empty, length: 80 }]);
+    }
+
+    #[test]
+    fn extended_runs_round_trip() {
+        let mut image: IndexedImage<256, 16> = IndexedImage::new();
+        for x in 0..16 {
+            image[(x, 12)] = ColorIndex::Skin;
+        }
+        image[(0, 0)] = ColorIndex::Dark;
+        image[(3, 9)] = ColorIndex::Bright;
+        let classic = indexed_to_rle(&image);
+        let extended = indexed_to_rle_with_format(&image, EXTENDED);
+        assert!(extended.bytes.len() < classic.bytes.len());
+        assert_eq!(rle_to_indexed::<256, 16>(&extended, 0), image);
+    }
+
+    #[test]
+    fn sample_has_no_long_runs() {
+        assert_eq!(indexed_to_rle(&sample()).bytes.len(), 53);
+        // only the format prefix is added
+        assert_eq!(indexed_to_rle_with_format(&sample(), EXTENDED).bytes.len(), 53 + 2);
+    }
+
+    const CONTROL_OPS: RleFormat = RleFormat {
+        extended_runs: false,
+        control_ops: true,
+        row_refs: false,
+        mirror: Mirror::None,
+        scan: ScanOrder::RowMajor,
+    };
+
+    #[test]
+    fn formats_are_written_in_the_prefix() {
+        let classic = indexed_to_rle(&sample());
+        assert_ne!(classic.bytes[0], FORMAT_MARKER);
+
+        let rle = indexed_to_rle_with_format(&sparse(), CONTROL_OPS);
+        assert_eq!(rle.bytes[..2], [FORMAT_MARKER, CONTROL_OPS.to_byte()]);
+        let read = OneByteRle::new_with_bytes(rle.bytes.clone()).unwrap();
+        assert_eq!(read.format, CONTROL_OPS);
+        assert_eq!((read.header_offset, read.header_width), (rle.header_offset, rle.header_width));
+        assert_eq!(rle_to_indexed::<256, 16>(&read, 0), sparse());
+    }
+
+    #[test]
+    fn classic_decoders_refuse_prefixed_streams() {
+        let rle = indexed_to_rle_with_format(&sparse(), CONTROL_OPS);
+        assert!(RleDecoder::with_format(&rle.bytes, RleFormat::default(), 0).is_none());
+        // and the classic stream still decodes either way
+        let classic = indexed_to_rle(&sparse());
+        assert!(RleDecoder::with_format(&classic.bytes, RleFormat::default(), 0).is_some());
+    }
+
+    #[test]
+    fn invalid_prefixes_are_rejected() {
+        assert!(OneByteRle::new_with_bytes(vec![FORMAT_MARKER]).is_none());
+        // prefix without a header
+        assert!(OneByteRle::new_with_bytes(vec![FORMAT_MARKER, CONTROL_OPS.to_byte()]).is_none());
+        assert!(OneByteRle::new_with_bytes(vec![FORMAT_MARKER, 0x80, 0x0F]).is_none());
+        assert!(OneByteRle::new_with_bytes(vec![FORMAT_MARKER, 0b11 << 3, 0x0F]).is_none());
+    }
+
+    #[test]
+    fn control_ops_end_rows() {
+        let mut rle = OneByteRle::with_format(CONTROL_OPS);
+        rle.push_header(0, 15);
+        // a pixel, the rest of its row and 3 more rows empty, then another pixel
+        rle.push_runs(&[(ColorIndex::Dark, 1), (ColorIndex::Empty, 15 + 16 * 3), (ColorIndex::Skin, 1)]);
+        assert_eq!(rle.run_bytes(), [0x20, 0x13, 0x60]);
+        let tokens: Vec<_> = rle.tokens().collect();
+        assert_eq!(tokens[1], RleToken::EndOfRow { skip_rows: 3 });
+
+        let image: IndexedImage<256, 16> = rle_to_indexed(&rle, 0);
+        assert_eq!(image[(0, 0)], ColorIndex::Dark);
+        assert_eq!(image[(0, 4)], ColorIndex::Skin);
+    }
+
+    #[test]
+    fn control_ops_round_trip() {
+        for image in [sample(), sparse()] {
+            let rle = indexed_to_rle_with_format(&image, CONTROL_OPS);
+            assert_eq!(rle_to_indexed::<256, 16>(&rle, 0), image);
+            let optimized = indexed_to_rle_optimized(&image, CONTROL_OPS).unwrap();
+            assert_eq!(rle_to_indexed::<256, 16>(&optimized.rle, optimized.vertical_trim), image);
+        }
+    }
+
+    #[test]
+    fn reserved_opcodes_stop_decoding() {
+        let mut bytes = vec![FORMAT_MARKER, CONTROL_OPS.to_byte(), 0x0F, 0x20];
+        bytes.extend([REPEAT_ROW_OP, 0x20]);
+        let rle = OneByteRle::new_with_bytes(bytes).unwrap();
+        assert_eq!(rle.tokens().count(), 1);
+    }
+
+    const ROW_REFS: RleFormat = RleFormat {
+        extended_runs: false,
+        control_ops: true,
+        row_refs: true,
+        mirror: Mirror::None,
+        scan: ScanOrder::RowMajor,
+    };
+
+    /// a 12 pixel wide pattern repeated down 14 rows, with every 5th row shifted in color
+    fn repeated_rows() -> IndexedImage<256, 16> {
+        let pattern = [1, 2, 1, 3, 3, 0, 4, 4, 0, 1, 2, 1];
+        let mut image = IndexedImage::new();
+        for y in 2..16 {
+            for (x, color) in pattern.iter().enumerate() {
+                let color = if y % 5 == 0 { (color + 1) % 8 } else { *color };
+                image[(x + 2, y)] = ColorIndex::from_u8(color).unwrap();
+            }
+        }
+        image.vertical_trim = 2;
+        image
+    }
+
+    #[test]
+    fn row_refs_round_trip() {
+        let image = repeated_rows();
+        let rle = indexed_to_rle_with_format(&image, ROW_REFS);
+        assert!(rle.tokens().any(|token| matches!(token, RleToken::CopyRows { back: 1, .. })));
+        assert!(rle.tokens().any(|token| matches!(token, RleToken::CopyRows { back: 5, count: 1 })));
+        let mut decoded: IndexedImage<256, 16> = rle_to_indexed(&rle, 2);
+        decoded.vertical_trim = 2;
+        assert_eq!(decoded, image);
+        // readme numbers
+        assert_eq!(indexed_to_rle(&image).bytes.len(), 133);
+        assert_eq!(rle.bytes.len(), 34);
+    }
+
+    #[test]
+    fn row_refs_imply_control_ops() {
+        let refs_only = RleFormat { control_ops: false, ..ROW_REFS };
+        let rle = indexed_to_rle_with_format(&repeated_rows(), refs_only);
+        assert_eq!(rle.bytes, indexed_to_rle_with_format(&repeated_rows(), ROW_REFS).bytes);
+        assert_eq!(OneByteRle::new_with_bytes(rle.bytes.clone()).unwrap().format, ROW_REFS);
+        // no opcode gets read back as an empty run
+        let tokens: Vec<_> = RleTokens::new(rle.run_bytes(), refs_only).collect();
+        assert_eq!(tokens, rle.tokens().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn row_refs_need_a_row_to_copy() {
+        // copying the previous row before the first one
+        let rle = OneByteRle::new_with_bytes(vec![FORMAT_MARKER, ROW_REFS.to_byte(), 0x0F, REPEAT_ROW_OP]).unwrap();
+        assert_eq!(RleDecoder::from_rle(&rle, 0).unwrap().count(), 0);
+        // copy row opcode without its row count
+        let rle = OneByteRle::new_with_bytes(vec![FORMAT_MARKER, ROW_REFS.to_byte(), 0x0F, COPY_ROW_OP]).unwrap();
+        assert_eq!(rle.tokens().count(), 0);
+        // or reaching further than 32 rows back
+        let rle = OneByteRle::new_with_bytes(vec![FORMAT_MARKER, ROW_REFS.to_byte(), 0x0F, COPY_ROW_OP, 32]).unwrap();
+        assert_eq!(rle.tokens().count(), 0);
+    }
+
+    const MIRROR: RleFormat = RleFormat {
+        extended_runs: false,
+        control_ops: false,
+        row_refs: false,
+        mirror: Mirror::Even,
+        scan: ScanOrder::RowMajor,
+    };
+
+    /// decodes from nothing but the bytes, so the format has to come from the stream
+    fn decode_bytes(rle: &OneByteRle, trim: u8) -> IndexedImage<256, 16> {
+        rle_to_indexed(&OneByteRle::new_with_bytes(rle.bytes.clone()).unwrap(), trim)
+    }
+
+    #[test]
+    fn sample_mirrors_around_its_centre_column() {
+        let optimized = indexed_to_rle_optimized(&sample(), MIRROR).unwrap();
+        assert_eq!(optimized.rle.format.mirror, Mirror::OddCenter);
+        assert_eq!(optimized.rle.bytes.len(), 36);
+        assert_eq!(decode_bytes(&optimized.rle, optimized.vertical_trim), sample());
+    }
+
+    #[test]
+    fn even_mirror_round_trips() {
+        let mut image: IndexedImage<256, 16> = IndexedImage::new();
+        for y in 4..12 {
+            for x in 3..7 {
+                let color = ColorIndex::from_u8(((x + y) % 8) as u8).unwrap();
+                image[(x, y)] = color;
+                image[(15 - x, y)] = color;
+            }
+        }
+        let rle = indexed_to_rle_with_format(&image, MIRROR);
+        assert_eq!(rle.format.mirror, Mirror::Even);
+        assert_eq!(decode_bytes(&rle, 0), image);
+    }
+
+    #[test]
+    fn asymmetric_images_stay_classic() {
+        let rle = indexed_to_rle_with_format(&sparse(), MIRROR);
+        assert_eq!(rle.format, RleFormat::default());
+        assert_eq!(rle.bytes, indexed_to_rle(&sparse()).bytes);
+    }
+
+    /// every other column filled
+    fn vertical_stripes() -> IndexedImage<256, 16> {
+        let mut image = IndexedImage::new();
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            if x % 2 == 0 {
+                *pixel = ColorIndex::Bright;
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn scan_orders_round_trip() {
+        for scan in ScanOrder::ALL {
+            for image in [sample(), sparse(), vertical_stripes()] {
+                let rle = indexed_to_rle_with_format(&image, RleFormat { scan, ..Default::default() });
+                assert_eq!(rle.format.scan, scan);
+                assert_eq!(decode_bytes(&rle, 0), image, "{scan:?}");
+
+                let with_ops = RleFormat { scan, ..ROW_REFS };
+                let rle = indexed_to_rle_with_format(&image, with_ops);
+                assert_eq!(decode_bytes(&rle, 0), image, "{scan:?} with row references");
+            }
+        }
+    }
+
+    #[test]
+    fn optimizer_picks_the_scan_order() {
+        let stripes = vertical_stripes();
+        assert_eq!(indexed_to_rle(&stripes).bytes.len(), 226);
+        let optimized = indexed_to_rle_optimized(&stripes, RleFormat::default()).unwrap();
+        assert_eq!(optimized.rle.format.scan, ScanOrder::ColumnMajor);
+        assert_eq!(optimized.rle.bytes.len(), 18);
+        assert_eq!(decode_bytes(&optimized.rle, optimized.vertical_trim), stripes);
+
+        // ties go to row major, which needs no format prefix
+        let optimized = indexed_to_rle_optimized(&sample(), RleFormat::default()).unwrap();
+        assert_eq!(optimized.rle.format, RleFormat::default());
+    }
+}
@@ -0,0 +1,338 @@
+use bevy::log::warn;
+use crate::types::{Oklab, Palette, PaletteCollection};
+
+/// A shared list of up to 256 colors that palettes can point into with one byte per color
+#[derive(Debug, Clone, PartialEq)]
+pub struct MasterPalette {
+    colors: Vec<image::Rgba<u8>>,
+}
+
+impl MasterPalette {
+    /// None for more than 256 colors
+    pub fn new(colors: Vec<image::Rgba<u8>>) -> Option<Self> {
+        (colors.len() <= 256).then_some(Self { colors })
+    }
+
+    /// Every distinct color of the collection, so it encodes without any error.
+    /// None if the collection has more than 256 distinct colors
+    pub fn from_collection<const C: usize>(collection: &PaletteCollection<u8, C>) -> Option<Self> {
+        let mut colors = vec![];
+        for color in collection.iter().flat_map(|palette| palette.colors()) {
+            if !colors.contains(color) {
+                colors.push(*color);
+            }
+        }
+        Self::new(colors)
+    }
+
+    pub fn colors(&self) -> &[image::Rgba<u8>] {
+        &self.colors
+    }
+
+    /// index of the closest color by Oklab distance, None if the master palette is empty
+    pub fn nearest(&self, color: &image::Rgba<u8>) -> Option<u8> {
+        let target = Oklab::from_rgba(color);
+        self.colors.iter()
+            .map(|c| Oklab::from_rgba(c).distance(&target))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i as u8)
+    }
+}
+
+/// the 216 color "web safe" cube, 6 levels per channel
+impl Default for MasterPalette {
+    fn default() -> Self {
+        let level = |i: usize| (i * 51) as u8;
+        let colors = (0..216)
+            .map(|i| image::Rgba([level(i / 36), level(i / 6 % 6), level(i % 6), 255]))
+            .collect();
+        Self { colors }
+    }
+}
+
+/// Smaller lossy ways to store palette colors than the 3 byte per color [`Palette::to_bytes`] pack.
+/// None of them keep alpha, every color decodes opaque
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteEncoding {
+    /// 5 bits red, 6 green, 5 blue, 2 bytes per color (big endian)
+    Rgb565,
+    /// 4 bits per channel, 1.5 bytes per color. Colors are packed back to back, two in every 3 bytes
+    Rgb444,
+    /// one byte per color, the index of the closest color in the master palette
+    Master(MasterPalette),
+}
+
+impl PaletteEncoding {
+    /// bytes taken by `colors` colors
+    pub fn encoded_len(&self, colors: usize) -> usize {
+        match self {
+            PaletteEncoding::Rgb565 => colors * 2,
+            PaletteEncoding::Rgb444 => (colors * 3).div_ceil(2),
+            PaletteEncoding::Master(_) => colors,
+        }
+    }
+
+    /// What a color looks like after going through the encoding
+    pub fn quantize(&self, color: &image::Rgba<u8>) -> image::Rgba<u8> {
+        match self {
+            PaletteEncoding::Rgb565 => rgb565_to_color(color_to_rgb565(color)),
+            PaletteEncoding::Rgb444 => rgb444_to_color(color_to_rgb444(color)),
+            PaletteEncoding::Master(master) => master.nearest(color)
+                .and_then(|i| master.colors.get(i as usize))
+                .map_or(*color, |c| image::Rgba([c.0[0], c.0[1], c.0[2], 255])),
+        }
+    }
+
+    /// Returns None (and warns) if a master palette is empty
+    pub fn encode_colors(&self, colors: &[image::Rgba<u8>]) -> Option<Vec<u8>> {
+        if colors.iter().any(|color| color.0[3] != 255) {
+            warn!("compact palette encodings have no alpha, translucent colors will decode opaque");
+        }
+        Some(match self {
+            PaletteEncoding::Rgb565 => colors.iter().flat_map(|color| color_to_rgb565(color).to_be_bytes()).collect(),
+            PaletteEncoding::Rgb444 => {
+                let mut out = Vec::with_capacity(self.encoded_len(colors.len()));
+                for pair in colors.chunks(2) {
+                    let first = color_to_rgb444(&pair[0]);
+                    // odd counts leave the last 4 bits empty
+                    let second = pair.get(1).map_or(0, color_to_rgb444);
+                    out.extend([(first >> 4) as u8, ((first & 0xF) << 4 | second >> 8) as u8, (second & 0xFF) as u8]);
+                }
+                out.truncate(self.encoded_len(colors.len()));
+                out
+            },
+            PaletteEncoding::Master(master) => {
+                let indices: Option<Vec<u8>> = colors.iter().map(|color| master.nearest(color)).collect();
+                if indices.is_none() {
+                    warn!("MASTER PALETTE IS EMPTY");
+                }
+                indices?
+            },
+        })
+    }
+
+    /// Reads `count` colors. Returns None if there aren't exactly enough bytes,
+    /// or an index points past the end of the master palette
+    pub fn decode_colors(&self, bytes: &[u8], count: usize) -> Option<Vec<image::Rgba<u8>>> {
+        if bytes.len() != self.encoded_len(count) {
+            warn!("compact palette is {} bytes, expected {}", bytes.len(), self.encoded_len(count));
+            return None;
+        }
+        Some(match self {
+            PaletteEncoding::Rgb565 => bytes.chunks_exact(2)
+                .map(|c| rgb565_to_color(u16::from_be_bytes([c[0], c[1]])))
+                .collect(),
+            PaletteEncoding::Rgb444 => (0..count)
+                .map(|i| {
+                    let start = i / 2 * 3;
+                    let value = match i % 2 {
+                        0 => (bytes[start] as u16) << 4 | (bytes[start + 1] >> 4) as u16,
+                        _ => ((bytes[start + 1] & 0xF) as u16) << 8 | bytes[start + 2] as u16,
+                    };
+                    rgb444_to_color(value)
+                })
+                .collect(),
+            PaletteEncoding::Master(master) => bytes.iter()
+                .map(|i| master.colors.get(*i as usize).map(|c| image::Rgba([c.0[0], c.0[1], c.0[2], 255])))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    pub fn encode_palette<const C: usize>(&self, palette: &Palette<u8, C>) -> Option<Vec<u8>> {
+        self.encode_colors(palette.colors())
+    }
+
+    pub fn decode_palette<const C: usize>(&self, bytes: &[u8]) -> Option<Palette<u8, C>> {
+        let colors = self.decode_colors(bytes, C)?;
+        Some(Palette::new(colors.try_into().ok()?))
+    }
+
+    /// All 8 palettes back to back, in palette order
+    pub fn encode_collection<const C: usize>(&self, collection: &PaletteCollection<u8, C>) -> Option<Vec<u8>> {
+        let colors: Vec<_> = collection.iter().flat_map(|palette| *palette.colors()).collect();
+        self.encode_colors(&colors)
+    }
+
+    pub fn decode_collection<const C: usize>(&self, bytes: &[u8]) -> Option<PaletteCollection<u8, C>> {
+        let colors = self.decode_colors(bytes, C * 8)?;
+        let palettes: Vec<_> = colors.chunks_exact(C)
+            .filter_map(|chunk| Some(Palette::new(chunk.try_into().ok()?)))
+            .collect();
+        Some(PaletteCollection::new(palettes.try_into().ok()?))
+    }
+
+    /// How far every color moves when encoded, see [`PaletteErrorReport`]
+    pub fn error_report(&self, colors: &[image::Rgba<u8>]) -> PaletteErrorReport {
+        let errors = colors.iter()
+            .map(|color| Oklab::from_rgba(color).distance(&Oklab::from_rgba(&self.quantize(color))))
+            .collect();
+        PaletteErrorReport::new(errors, self.encoded_len(colors.len()), colors.len() * 3)
+    }
+
+    pub fn palette_error<const C: usize>(&self, palette: &Palette<u8, C>) -> PaletteErrorReport {
+        self.error_report(palette.colors())
+    }
+
+    pub fn collection_error<const C: usize>(&self, collection: &PaletteCollection<u8, C>) -> PaletteErrorReport {
+        let colors: Vec<_> = collection.iter().flat_map(|palette| *palette.colors()).collect();
+        self.error_report(&colors)
+    }
+}
+
+/// Color error a [`PaletteEncoding`] adds, as Oklab distance x100 (around 2 is barely noticeable, see [`Oklab::distance`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteErrorReport {
+    /// error of every color, in palette order
+    pub errors: Vec<f32>,
+    pub max: f32,
+    pub mean: f32,
+    /// size with the encoding
+    pub bytes: usize,
+    /// size as 3 byte per color RGB
+    pub rgb_bytes: usize,
+}
+
+impl PaletteErrorReport {
+    fn new(errors: Vec<f32>, bytes: usize, rgb_bytes: usize) -> Self {
+        let max = errors.iter().copied().fold(0.0, f32::max);
+        let mean = if errors.is_empty() { 0.0 } else { errors.iter().sum::<f32>() / errors.len() as f32 };
+        Self {
+            errors,
+            max,
+            mean,
+            bytes,
+            rgb_bytes,
+        }
+    }
+
+    /// index (0 based, in palette order) of the color that moved the most
+    pub fn worst(&self) -> Option<usize> {
+        self.errors.iter().enumerate().max_by(|(_, a), (_, b)| a.total_cmp(b)).map(|(i, _)| i)
+    }
+}
+
+fn color_to_rgb565(color: &image::Rgba<u8>) -> u16 {
+    let [r, g, b, _] = color.0.map(|c| c as u16);
+    let (r, g, b) = ((r * 31 + 127) / 255, (g * 63 + 127) / 255, (b * 31 + 127) / 255);
+    r << 11 | g << 5 | b
+}
+
+/// bits are repeated into the low end, so full channels stay full
+fn rgb565_to_color(value: u16) -> image::Rgba<u8> {
+    let (r, g, b) = ((value >> 11) as u8, (value >> 5 & 0x3F) as u8, (value & 0x1F) as u8);
+    image::Rgba([r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255])
+}
+
+fn color_to_rgb444(color: &image::Rgba<u8>) -> u16 {
+    let [r, g, b, _] = color.0.map(|c| (c as u16 * 15 + 127) / 255);
+    r << 8 | g << 4 | b
+}
+
+fn rgb444_to_color(value: u16) -> image::Rgba<u8> {
+    let [r, g, b] = [value >> 8, value >> 4 & 0xF, value & 0xF].map(|c| c as u8 * 17);
+    image::Rgba([r, g, b, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encodings() -> [PaletteEncoding; 3] {
+        [PaletteEncoding::Rgb565, PaletteEncoding::Rgb444, PaletteEncoding::Master(MasterPalette::default())]
+    }
+
+    #[test]
+    fn palettes_decode_to_their_quantized_colors() {
+        let palette = Palette::<u8>::default();
+        for encoding in encodings() {
+            let bytes = encoding.encode_palette(&palette).unwrap();
+            assert_eq!(bytes.len(), encoding.encoded_len(7));
+            let back: Palette<u8> = encoding.decode_palette(&bytes).unwrap();
+            for (decoded, color) in back.colors().iter().zip(palette.colors()) {
+                assert_eq!(*decoded, encoding.quantize(color));
+            }
+            assert_eq!(encoding.decode_palette::<7>(&bytes[1..]), None);
+        }
+    }
+
+    #[test]
+    fn collections_round_trip() {
+        let collection = PaletteCollection::<u8>::from_seed(9);
+        for encoding in encodings() {
+            let bytes = encoding.encode_collection(&collection).unwrap();
+            assert_eq!(bytes.len(), encoding.encoded_len(56));
+            let back: PaletteCollection<u8> = encoding.decode_collection(&bytes).unwrap();
+            for (decoded, palette) in back.iter().zip(collection.iter()) {
+                for (a, b) in decoded.colors().iter().zip(palette.colors()) {
+                    assert_eq!(*a, encoding.quantize(b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rgb444_odd_counts() {
+        let colors = [image::Rgba([0x11, 0x22, 0x33, 255]), image::Rgba([0x44, 0x55, 0x66, 255]), image::Rgba([0xFF, 0x00, 0x88, 255])];
+        let bytes = PaletteEncoding::Rgb444.encode_colors(&colors).unwrap();
+        assert_eq!(bytes, [0x12, 0x34, 0x56, 0xF0, 0x80]);
+        assert_eq!(PaletteEncoding::Rgb444.decode_colors(&bytes, 3).unwrap(), colors);
+        assert_eq!(PaletteEncoding::Rgb444.encoded_len(1), 2);
+        assert_eq!(PaletteEncoding::Rgb444.decode_colors(&bytes, 4), None);
+    }
+
+    #[test]
+    fn full_channels_stay_full() {
+        let white = image::Rgba([255, 255, 255, 255]);
+        let black = image::Rgba([0, 0, 0, 255]);
+        for encoding in [PaletteEncoding::Rgb565, PaletteEncoding::Rgb444] {
+            assert_eq!(encoding.quantize(&white), white);
+            assert_eq!(encoding.quantize(&black), black);
+        }
+    }
+
+    #[test]
+    fn master_nearest() {
+        let master = MasterPalette::default();
+        assert_eq!(master.colors().len(), 216);
+        assert_eq!(master.nearest(&image::Rgba([0, 0, 0, 255])), Some(0));
+        assert_eq!(master.nearest(&image::Rgba([250, 250, 250, 255])), Some(215));
+        assert_eq!(master.nearest(&image::Rgba([255, 0, 0, 255])), Some(180));
+        assert_eq!(MasterPalette::new(vec![]).unwrap().nearest(&image::Rgba([0, 0, 0, 255])), None);
+        assert_eq!(MasterPalette::new(vec![image::Rgba([0, 0, 0, 255]); 257]), None);
+    }
+
+    #[test]
+    fn master_indices_past_the_end_are_rejected() {
+        let master = MasterPalette::new(vec![image::Rgba([0, 0, 0, 255]); 2]).unwrap();
+        let encoding = PaletteEncoding::Master(master);
+        assert!(encoding.decode_colors(&[0, 1], 2).is_some());
+        assert_eq!(encoding.decode_colors(&[0, 2], 2), None);
+        let empty = PaletteEncoding::Master(MasterPalette::new(vec![]).unwrap());
+        assert_eq!(empty.encode_colors(&[image::Rgba([0, 0, 0, 255])]), None);
+    }
+
+    #[test]
+    fn collection_master_palette_is_exact() {
+        let collection = PaletteCollection::<u8>::from_seed(9);
+        let master = MasterPalette::from_collection(&collection).unwrap();
+        let report = PaletteEncoding::Master(master).collection_error(&collection);
+        assert_eq!(report.max, 0.0);
+        assert_eq!(report.bytes, 56);
+        assert_eq!(report.rgb_bytes, 168);
+    }
+
+    #[test]
+    fn readme_error_report() {
+        let palette = Palette::<u8>::default();
+        let means: Vec<_> = encodings().iter().map(|encoding| format!("{:.2}", encoding.palette_error(&palette).mean)).collect();
+        assert_eq!(means, ["0.35", "0.89", "2.49"]);
+
+        let report = PaletteEncoding::Rgb565.palette_error(&palette);
+        assert_eq!((report.bytes, report.rgb_bytes), (14, 21));
+        assert_eq!(report.errors.len(), 7);
+        let worst = report.worst().unwrap();
+        assert_eq!(report.errors[worst], report.max);
+        assert_eq!(PaletteErrorReport::new(vec![], 0, 0).worst(), None);
+    }
+}
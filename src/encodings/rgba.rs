@@ -0,0 +1,234 @@
+use bytemuck::Contiguous;
+use crate::types::{ColorIndex, Palette};
+use super::{OneByteRle, PixelSink, RleDecoder, RleSpan};
+
+/// Palette colors packed as RGBA8 and indexed directly by the raw [`ColorIndex`] value,
+/// so decoding never has to go through the palette per pixel.
+/// Colors keep their palette alpha, Empty is always transparent black.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RgbaLut(pub [[u8; 4]; 8]);
+
+impl RgbaLut {
+    pub fn new(palette: &Palette<u8>) -> Self {
+        let mut lut = [[0; 4]; 8];
+        for (i, entry) in lut.iter_mut().enumerate().skip(1) {
+            // every value from 1 to 7 is a valid color
+            let color = ColorIndex::from_integer(i as u8).unwrap_or_default();
+            *entry = palette[color].0;
+        }
+        Self(lut)
+    }
+
+    #[inline(always)]
+    pub fn get(&self, color: ColorIndex) -> [u8; 4] {
+        self.0[color as usize]
+    }
+}
+
+impl From<&Palette<u8>> for RgbaLut {
+    fn from(palette: &Palette<u8>) -> Self {
+        Self::new(palette)
+    }
+}
+
+/// Draws straight alpha `src` over `dst` (the usual "source over" blend).
+/// Opaque colors replace `dst` and fully transparent ones leave it as is
+pub fn blend_over(dst: &mut [u8], src: [u8; 4]) {
+    match src[3] {
+        255 => dst.copy_from_slice(&src),
+        0 => {},
+        src_a => {
+            let src_a = src_a as u32;
+            // dst alpha weighted by what's left over from src
+            let dst_a = dst[3] as u32 * (255 - src_a) / 255;
+            let out_a = src_a + dst_a;
+            for i in 0..3 {
+                dst[i] = ((src[i] as u32 * src_a + dst[i] as u32 * dst_a + out_a / 2) / out_a) as u8;
+            }
+            dst[3] = out_a as u8;
+        },
+    }
+}
+
+/// Writes decoded pixels into a tightly packed RGBA8 buffer,
+/// drawing each pixel as a `scale` x `scale` block.
+/// The buffer holds `width * scale` by `height * scale` pixels, where
+/// width and height are the size of the canvas the asset is decoded onto.
+/// Pixels outside of the canvas or buffer are dropped.
+pub struct RgbaSink<'a> {
+    buffer: &'a mut [u8],
+    lut: &'a RgbaLut,
+    width: usize,
+    height: usize,
+    scale: usize,
+    /// blend colors over the buffer instead of overwriting it
+    layered: bool,
+}
+
+impl<'a> RgbaSink<'a> {
+    pub fn new(buffer: &'a mut [u8], lut: &'a RgbaLut, width: usize, height: usize, scale: usize) -> Self {
+        debug_assert!(scale > 0);
+        Self {
+            buffer,
+            lut,
+            width,
+            height,
+            scale,
+            layered: false,
+        }
+    }
+
+    /// Sink for drawing an asset on top of what's already in the buffer:
+    /// Empty pixels are skipped and colors are blended with [`blend_over`],
+    /// so semi transparent palette colors (shadows, glass) show the layers below them
+    pub fn layered(buffer: &'a mut [u8], lut: &'a RgbaLut, width: usize, height: usize, scale: usize) -> Self {
+        Self {
+            layered: true,
+            ..Self::new(buffer, lut, width, height, scale)
+        }
+    }
+}
+
+impl<'a> PixelSink for RgbaSink<'a> {
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex) {
+        self.put_span(RleSpan { x, y, length: 1, color });
+    }
+
+    fn put_span(&mut self, span: RleSpan) {
+        if span.y >= self.height || span.x >= self.width {
+            return;
+        }
+        if self.layered && span.color == ColorIndex::Empty {
+            return;
+        }
+        let length = usize::min(span.length, self.width - span.x);
+        let rgba = self.lut.get(span.color);
+        let row_bytes = self.width * self.scale * 4;
+
+        for sub_y in 0..self.scale {
+            let start = (span.y * self.scale + sub_y) * row_bytes + span.x * self.scale * 4;
+            let end = start + length * self.scale * 4;
+            if let Some(row) = self.buffer.get_mut(start..end) {
+                for pixel in row.chunks_exact_mut(4) {
+                    if self.layered {
+                        blend_over(pixel, rgba);
+                    } else {
+                        pixel.copy_from_slice(&rgba);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Decodes an encoding straight into a RGBA8 buffer, skipping the [`crate::types::IndexedImage`] step.
+/// See [`RgbaSink`] for the buffer layout, raw bytes can be decoded the same way
+/// by handing a sink to [`super::decode_into_sink`]. Only pixels inside the header window are written,
+/// so clear the buffer first. Returns false if there was no header to decode
+pub fn rle_to_rgba(rle: &OneByteRle, trim: u8, lut: &RgbaLut, buffer: &mut [u8], width: usize, height: usize, scale: usize) -> bool {
+    match RleDecoder::from_rle(rle, trim) {
+        Some(decoder) => {
+            decoder.decode_into(&mut RgbaSink::new(buffer, lut, width, height, scale));
+            true
+        },
+        None => false,
+    }
+}
+
+/// Same as [`rle_to_rgba`] but draws the asset over the buffer's current contents (see [`RgbaSink::layered`]),
+/// call it once per layer from the bottom up. Returns false if there was no header to decode
+pub fn rle_on_rgba(rle: &OneByteRle, trim: u8, lut: &RgbaLut, buffer: &mut [u8], width: usize, height: usize, scale: usize) -> bool {
+    match RleDecoder::from_rle(rle, trim) {
+        Some(decoder) => {
+            decoder.decode_into(&mut RgbaSink::layered(buffer, lut, width, height, scale));
+            true
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::{sample, sample_rle};
+
+    /// what the indexed decode plus a palette lookup gives for a pixel
+    fn expected(palette: &Palette<u8>, color: ColorIndex) -> [u8; 4] {
+        match color {
+            ColorIndex::Empty => [0, 0, 0, 0],
+            color => palette[color].0,
+        }
+    }
+
+    #[test]
+    fn matches_indexed_decode() {
+        let palette = Palette::default();
+        let lut = RgbaLut::new(&palette);
+        let image = sample();
+        for scale in [1, 3] {
+            let size = 16 * scale;
+            // only the header window is written, the rest stays cleared
+            let mut buffer = vec![0; size * size * 4];
+            assert!(rle_to_rgba(&sample_rle(), 0, &lut, &mut buffer, 16, 16, scale));
+            for (i, pixel) in buffer.chunks_exact(4).enumerate() {
+                let (x, y) = (i % size / scale, i / size / scale);
+                assert_eq!(pixel, expected(&palette, image[(x, y)]), "scale {scale} at {x},{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_is_transparent_black() {
+        let mut palette = Palette::default();
+        palette[ColorIndex::Dark] = image::Rgba([1, 2, 3, 4]);
+        let lut = RgbaLut::new(&palette);
+        assert_eq!(lut.get(ColorIndex::Empty), [0, 0, 0, 0]);
+        assert_eq!(lut.get(ColorIndex::Dark), [1, 2, 3, 4]);
+
+        let mut buffer = [0xFF; 4];
+        RgbaSink::new(&mut buffer, &lut, 1, 1, 1).put_pixel(0, 0, ColorIndex::Empty);
+        assert_eq!(buffer, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn blend_over_alpha() {
+        let src = [255, 255, 255, 128];
+        // opaque src replaces, transparent src does nothing
+        let mut dst = [10, 20, 30, 40];
+        blend_over(&mut dst, [1, 2, 3, 255]);
+        assert_eq!(dst, [1, 2, 3, 255]);
+        blend_over(&mut dst, [200, 200, 200, 0]);
+        assert_eq!(dst, [1, 2, 3, 255]);
+
+        // half over opaque black stays opaque and lands halfway
+        let mut dst = [0, 0, 0, 255];
+        blend_over(&mut dst, src);
+        assert_eq!(dst, [128, 128, 128, 255]);
+
+        // over nothing it's just the src, not darkened by the transparent black
+        let mut dst = [0, 0, 0, 0];
+        blend_over(&mut dst, src);
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    fn spans_past_the_canvas_are_clipped() {
+        let lut = RgbaLut::new(&Palette::default());
+        let color = lut.get(ColorIndex::Skin);
+        // 4x2 canvas at scale 2
+        let mut buffer = vec![0; 8 * 4 * 4];
+        let mut sink = RgbaSink::new(&mut buffer, &lut, 4, 2, 2);
+        sink.put_span(RleSpan { x: 2, y: 1, length: 30, color: ColorIndex::Skin });
+        sink.put_span(RleSpan { x: 0, y: 5, length: 4, color: ColorIndex::Skin });
+        sink.put_span(RleSpan { x: 9, y: 0, length: 4, color: ColorIndex::Skin });
+        for (i, pixel) in buffer.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 8, i / 8);
+            let inside = x >= 4 && y >= 2;
+            assert_eq!(pixel, if inside { color } else { [0; 4] }, "{x},{y}");
+        }
+
+        // a buffer smaller than the canvas drops what doesn't fit
+        let mut small = vec![0; 4];
+        assert!(rle_to_rgba(&sample_rle(), 0, &lut, &mut small, 16, 16, 1));
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use serde::{de::{self, Visitor, SeqAccess}, Deserialize, Deserializer, Serialize, Serializer};
+use super::{OneByteRle, TextEncoding};
+
+/// Human readable formats (JSON, TOML) get the base64url text form, binary formats get the raw bytes.
+/// The format travels in the format prefix of the bytes, like it does in the text forms
+impl Serialize for OneByteRle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_text(TextEncoding::Base64Url))
+        } else {
+            serializer.serialize_bytes(&self.bytes)
+        }
+    }
+}
+
+/// Accepts any of the text forms, so hand written hex works in config files too,
+/// as well as lists of byte values
+impl<'de> Deserialize<'de> for OneByteRle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(OneByteRleVisitor)
+        } else {
+            deserializer.deserialize_bytes(OneByteRleVisitor)
+        }
+    }
+}
+
+struct OneByteRleVisitor;
+
+impl OneByteRleVisitor {
+    fn from_bytes<E: de::Error>(bytes: Vec<u8>) -> Result<OneByteRle, E> {
+        OneByteRle::new_with_bytes(bytes).ok_or_else(|| E::invalid_length(0, &"at least a header byte"))
+    }
+}
+
+impl<'de> Visitor<'de> for OneByteRleVisitor {
+    type Value = OneByteRle;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rle bytes, or a hex, base64url or base58 string of them")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Self::from_bytes(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Self::from_bytes(v)
+    }
+
+    /// some formats write bytes as a list of numbers
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Self::from_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encodings::onebyte_rle::tests::{sample, SAMPLE};
+    use crate::encodings::{indexed_to_rle_optimized, rle_to_indexed, Mirror, OneByteRle, RleFormat, TextEncoding};
+
+    #[test]
+    fn json_keeps_the_format() {
+        let format = RleFormat { extended_runs: true, mirror: Mirror::Even, ..Default::default() };
+        let optimized = indexed_to_rle_optimized(&sample(), format).unwrap();
+        assert_ne!(optimized.rle.format, RleFormat::default());
+        let json = serde_json::to_string(&optimized.rle).unwrap();
+        assert_eq!(json, format!("\"{}\"", optimized.rle.to_text(TextEncoding::Base64Url)));
+
+        let back: OneByteRle = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.bytes, optimized.rle.bytes);
+        assert_eq!(back.format, optimized.rle.format);
+        assert_eq!(rle_to_indexed::<256, 16>(&back, optimized.vertical_trim), sample());
+    }
+
+    #[test]
+    fn json_reads_hex_and_byte_lists() {
+        let from_hex: OneByteRle = serde_json::from_str(&format!("\"{SAMPLE}\"")).unwrap();
+        assert_eq!(from_hex.to_string(), SAMPLE);
+        let list = serde_json::to_string(&from_hex.bytes).unwrap();
+        let from_list: OneByteRle = serde_json::from_str(&list).unwrap();
+        assert_eq!(from_list.bytes, from_hex.bytes);
+    }
+
+    #[test]
+    fn json_rejects_invalid() {
+        for json in ["\"\"", "[]", "\"fe\"", "\"not hex\""] {
+            assert!(serde_json::from_str::<OneByteRle>(json).is_err(), "{json}");
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::types::{ColorIndex, IndexedImage};
+use bevy::log::warn;
+use super::{split_format, Mirror, OneByteRle, RleFormat, RleToken, RleTokens, ScanOrder, ROW_REF_LIMIT};
+
+// widest row a header can describe
+const MAX_ROW_WIDTH: usize = 0x1 << 5;
+
+/// A horizontal run of decoded pixels. Never wraps onto the next row,
+/// runs that do are split into one span per row.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RleSpan {
+    pub x: usize,
+    pub y: usize,
+    pub length: usize,
+    pub color: ColorIndex,
+}
+
+/// Anything decoded pixels can be written into without building an [`IndexedImage`] first.
+/// Ex. framebuffers, RGBA buffers or bit masks
+pub trait PixelSink {
+    /// Writes a single pixel. Pixels outside of the sink should be ignored
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex);
+
+    /// Writes a whole span. Override this when a row can be filled faster than pixel by pixel
+    fn put_span(&mut self, span: RleSpan) {
+        for i in 0..span.length {
+            self.put_pixel(span.x + i, span.y, span.color);
+        }
+    }
+}
+
+/// Streams [`RleSpan`]s straight out of encoded bytes, format prefix and header included.
+/// Nothing is allocated while decoding. Streams with row references
+/// keep the last 32 rows in a fixed buffer so they can be copied again.
+/// Mirrored streams give the reflected span right after each decoded one.
+/// Column major streams give one span per pixel, since spans are always horizontal.
+pub struct RleDecoder<'a> {
+    tokens: RleTokens<'a>,
+    offset: usize,
+    /// length of a line, which is the window height for column major scans
+    width: usize,
+    trim: usize,
+    /// pixels decoded so far
+    position: usize,
+    /// color and pixels left over from the run being split into spans
+    pending: (ColorIndex, usize),
+    /// rows back to copy from, and rows left to copy
+    pending_copy: (usize, usize),
+    /// ring buffer of the last decoded rows, only filled in when the format has row references
+    history: Option<[[ColorIndex; MAX_ROW_WIDTH]; ROW_REF_LIMIT]>,
+    mirror: Mirror,
+    scan: ScanOrder,
+    /// reflection of the last span, given out next
+    mirrored: Option<RleSpan>,
+}
+
+impl<'a> RleDecoder<'a> {
+    /// Starts decoding the bytes at row `trim`, in the format their prefix names (classic without one).
+    /// Returns None if the prefix or header is invalid, see [`RleDecoder::with_format`]
+    pub fn new(bytes: &'a [u8], trim: u8) -> Option<Self> {
+        let (format, stream) = split_format(bytes)?;
+        Self::with_format(stream, format, trim)
+    }
+
+    /// Starts decoding bytes that have no format prefix as if they were written with `format`.
+    /// With the classic format this reads streams the way decoders from before format prefixes do,
+    /// which refuses prefixed streams since the marker is not a valid header.
+    /// Returns None if there is no header byte, or the header's window is wider than 32 pixels
+    pub fn with_format(bytes: &'a [u8], format: RleFormat, trim: u8) -> Option<Self> {
+        let (header, run_bytes) = bytes.split_first()?;
+        // same layout as OneByteRle::get_header_from_byte, without the logging
+        let offset = (header >> 5) as usize;
+        let width = (header & 0x1F) as usize + 1;
+        // column major headers hold the column height, which isn't limited by the offset
+        if format.scan != ScanOrder::ColumnMajor && offset + width > MAX_ROW_WIDTH {
+            warn!("RLE HEADER {header:#04x} DOES NOT FIT IN A {MAX_ROW_WIDTH} PIXEL ROW");
+            return None;
+        }
+        Some(Self {
+            tokens: RleTokens::new(run_bytes, format),
+            offset,
+            width,
+            trim: trim as usize,
+            position: 0,
+            pending: (ColorIndex::Empty, 0),
+            pending_copy: (0, 0),
+            history: format.row_refs.then_some([[ColorIndex::Empty; MAX_ROW_WIDTH]; ROW_REF_LIMIT]),
+            // only row major scans can be mirrored
+            mirror: if format.scan == ScanOrder::RowMajor { format.mirror } else { Mirror::None },
+            scan: format.scan,
+            mirrored: None,
+        })
+    }
+
+    pub fn from_rle(rle: &'a OneByteRle, trim: u8) -> Option<Self> {
+        Self::new(&rle.bytes, trim)
+    }
+
+    /// Iterates every decoded pixel as `(x, y, color)`, Empty ones included
+    pub fn pixels(self) -> impl Iterator<Item = (usize, usize, ColorIndex)> + 'a {
+        self.flat_map(|span| (span.x..span.x + span.length).map(move |x| (x, span.y, span.color)))
+    }
+
+    /// Writes every remaining span into the sink
+    pub fn decode_into<S: PixelSink + ?Sized>(self, sink: &mut S) {
+        for span in self {
+            sink.put_span(span);
+        }
+    }
+
+    /// stops decoding for good
+    fn fail(&mut self, reason: &str) -> Option<RleSpan> {
+        warn!("{reason}");
+        self.tokens = RleTokens::new(&[], RleFormat::default());
+        self.pending = (ColorIndex::Empty, 0);
+        self.pending_copy = (0, 0);
+        self.mirrored = None;
+        None
+    }
+
+    /// Emits the next span of a row copy, made of matching pixels from the source row
+    fn next_copied_span(&mut self) -> Option<RleSpan> {
+        let (back, _) = self.pending_copy;
+        let row = self.position / self.width;
+        let column = self.position % self.width;
+        let source = match (&self.history, row.checked_sub(back)) {
+            (Some(history), Some(source_row)) => &history[source_row % ROW_REF_LIMIT],
+            _ => return self.fail("ENCOUNTERED ROW REFERENCE BEFORE THE FIRST ROW WHILE DECODING RLE"),
+        };
+
+        let color = source[column];
+        let length = self.span_length(source[column..self.width].iter().take_while(|p| **p == color).count());
+
+        // moving onto a new row when this one is done
+        if column + length == self.width {
+            self.pending_copy.1 -= 1;
+        }
+        Some(self.emit(color, length))
+    }
+
+    /// caps a run along the current line to what a single span can hold
+    fn span_length(&self, length: usize) -> usize {
+        if self.scan == ScanOrder::ColumnMajor { usize::min(length, 1) } else { length }
+    }
+
+    /// Builds the span at the current position and moves past it
+    fn emit(&mut self, color: ColorIndex, length: usize) -> RleSpan {
+        let row = self.position / self.width;
+        let column = self.position % self.width;
+
+        if let Some(history) = &mut self.history {
+            history[row % ROW_REF_LIMIT][column..column + length].fill(color);
+        }
+        self.position += length;
+
+        if self.mirror != Mirror::None {
+            // the centre column of an odd mirror is only drawn once
+            let centre = self.mirror == Mirror::OddCenter && column + length == self.width;
+            let mirrored_length = if centre { length - 1 } else { length };
+            if mirrored_length > 0 {
+                self.mirrored = Some(RleSpan {
+                    x: self.offset + self.mirror.full_width(self.width) - column - mirrored_length,
+                    y: self.trim + row,
+                    length: mirrored_length,
+                    color,
+                });
+            }
+        }
+
+        match self.scan {
+            ScanOrder::RowMajor => RleSpan {
+                x: self.offset + column,
+                y: self.trim + row,
+                length,
+                color,
+            },
+            // odd rows run right to left
+            ScanOrder::Serpentine => RleSpan {
+                x: self.offset + if row % 2 == 1 { self.width - column - length } else { column },
+                y: self.trim + row,
+                length,
+                color,
+            },
+            // lines are columns, and spans are a single pixel
+            ScanOrder::ColumnMajor => RleSpan {
+                x: self.offset + row,
+                y: self.trim + column,
+                length,
+                color,
+            },
+        }
+    }
+}
+
+impl<'a> Iterator for RleDecoder<'a> {
+    type Item = RleSpan;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(span) = self.mirrored.take() {
+            return Some(span);
+        }
+
+        while self.pending.1 == 0 {
+            if self.pending_copy.1 > 0 {
+                return self.next_copied_span();
+            }
+            self.pending = match self.tokens.next()? {
+                RleToken::Run { color, length } => (color, length as usize),
+                RleToken::EndOfRow { skip_rows } => {
+                    // empty pixels up to the end of this row, then the skipped rows
+                    let row_rest = self.width - self.position % self.width;
+                    (ColorIndex::Empty, row_rest + skip_rows as usize * self.width)
+                },
+                RleToken::CopyRows { back, count } => {
+                    if !self.position.is_multiple_of(self.width) {
+                        return self.fail("ENCOUNTERED ROW REFERENCE IN THE MIDDLE OF A ROW WHILE DECODING RLE");
+                    }
+                    self.pending_copy = (back as usize, count as usize);
+                    continue;
+                },
+            };
+        }
+
+        let column = self.position % self.width;
+        let (color, remaining) = self.pending;
+        let length = self.span_length(usize::min(remaining, self.width - column));
+        self.pending.1 -= length;
+
+        Some(self.emit(color, length))
+    }
+}
+
+/// Decodes raw bytes into any sink. Returns false if there was no valid header to decode
+pub fn decode_into_sink<S: PixelSink + ?Sized>(bytes: &[u8], trim: u8, sink: &mut S) -> bool {
+    match RleDecoder::new(bytes, trim) {
+        Some(decoder) => {
+            decoder.decode_into(sink);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Writes pixels inside the image bounds, Empty included
+impl<const N: usize, const W: usize> PixelSink for IndexedImage<N, W> {
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex) {
+        if x < W && y < N / W {
+            self[(x, y)] = color;
+        }
+    }
+}
+
+/// Lets a closure act as a sink
+impl<F: FnMut(usize, usize, ColorIndex)> PixelSink for F {
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex) {
+        self(x, y, color)
+    }
+}
+
+/// Passes everything except Empty pixels on to the inner sink,
+/// so decoded assets can be layered on top of each other
+pub struct SkipEmpty<'s, S: PixelSink + ?Sized>(pub &'s mut S);
+
+impl<'s, S: PixelSink + ?Sized> PixelSink for SkipEmpty<'s, S> {
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex) {
+        if color != ColorIndex::Empty {
+            self.0.put_pixel(x, y, color);
+        }
+    }
+
+    fn put_span(&mut self, span: RleSpan) {
+        if span.color != ColorIndex::Empty {
+            self.0.put_span(span);
+        }
+    }
+}
+
+/// Row major 1 bit per pixel mask, most significant bit first (same layout as the Sq 1bit encoding).
+/// Bits are set for colored pixels and cleared for Empty ones
+pub struct BitMaskSink<'a> {
+    pub bits: &'a mut [u8],
+    pub width: usize,
+}
+
+impl<'a> PixelSink for BitMaskSink<'a> {
+    fn put_pixel(&mut self, x: usize, y: usize, color: ColorIndex) {
+        if x >= self.width {
+            return;
+        }
+        let index = y * self.width + x;
+        if let Some(byte) = self.bits.get_mut(index / 8) {
+            let bit = 0x80 >> (index % 8);
+            if color == ColorIndex::Empty {
+                *byte &= !bit;
+            } else {
+                *byte |= bit;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::{sample, sparse, SAMPLE};
+    use crate::encodings::{indexed_to_rle_optimized, indexed_to_rle_with_format, rle_to_indexed};
+
+    fn formats() -> Vec<RleFormat> {
+        vec![
+            RleFormat::default(),
+            RleFormat { extended_runs: true, control_ops: true, ..Default::default() },
+            RleFormat { row_refs: true, ..Default::default() },
+            RleFormat { mirror: Mirror::Even, ..Default::default() },
+            RleFormat { scan: ScanOrder::ColumnMajor, ..Default::default() },
+            RleFormat { scan: ScanOrder::Serpentine, ..Default::default() },
+        ]
+    }
+
+    #[test]
+    fn matches_the_image_decoder() {
+        for image in [sample(), sparse()] {
+            for format in formats() {
+                let Some(optimized) = indexed_to_rle_optimized(&image, format) else { continue };
+                let mut decoded: IndexedImage<256, 16> = IndexedImage::new();
+                assert!(decode_into_sink(&optimized.rle.bytes, optimized.vertical_trim, &mut decoded));
+                assert_eq!(decoded, image, "{format:?}");
+                assert_eq!(decoded, rle_to_indexed(&optimized.rle, optimized.vertical_trim));
+            }
+        }
+    }
+
+    #[test]
+    fn spans_stay_within_their_row() {
+        for format in formats() {
+            let rle = indexed_to_rle_with_format(&sample(), format);
+            let decoder = RleDecoder::from_rle(&rle, 0).unwrap();
+            for span in decoder {
+                assert!(span.length > 0);
+                assert!(span.x + span.length <= 16, "{format:?} {span:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_cover_the_window() {
+        let bytes = hex::decode(SAMPLE).unwrap();
+        let decoder = RleDecoder::new(&bytes, 0).unwrap();
+        let colored = decoder.pixels().filter(|(_, _, color)| *color != ColorIndex::Empty).count();
+        assert_eq!(colored, sample().enumerate_pixels().filter(|(_, _, p)| **p != ColorIndex::Empty).count());
+    }
+
+    #[test]
+    fn skip_empty_layers_over() {
+        let bytes = hex::decode(SAMPLE).unwrap();
+        let mut layered: IndexedImage<256, 16> = IndexedImage::new();
+        for (x, y) in (0..16).flat_map(|y| (0..16).map(move |x| (x, y))) {
+            layered[(x, y)] = ColorIndex::Accent4;
+        }
+        assert!(decode_into_sink(&bytes, 0, &mut SkipEmpty(&mut layered)));
+        for (x, y, p) in sample().enumerate_pixels() {
+            let expected = if *p == ColorIndex::Empty { ColorIndex::Accent4 } else { *p };
+            assert_eq!(layered[(x as usize, y as usize)], expected);
+        }
+    }
+
+    #[test]
+    fn bit_mask_sink() {
+        let bytes = hex::decode(SAMPLE).unwrap();
+        let mut bits = [0u8; 32];
+        assert!(decode_into_sink(&bytes, 0, &mut BitMaskSink { bits: &mut bits, width: 16 }));
+        for (x, y, p) in sample().enumerate_pixels() {
+            let index = y as usize * 16 + x as usize;
+            let set = bits[index / 8] & (0x80 >> (index % 8)) != 0;
+            assert_eq!(set, *p != ColorIndex::Empty);
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_oversized_headers() {
+        let mut sink = |_: usize, _: usize, _: ColorIndex| panic!("nothing should be decoded");
+        assert!(!decode_into_sink(&[], 0, &mut sink));
+        // offset 7 with width 31 doesn't fit a 32 pixel row
+        assert!(RleDecoder::with_format(&[0xFE, 0x20], RleFormat::default(), 0).is_none());
+    }
+}
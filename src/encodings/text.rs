@@ -0,0 +1,225 @@
+use std::{fmt::Display, str::FromStr};
+use super::{split_format, OneByteRle};
+
+// prefixes follow multibase. Hex has none, so every existing hex string still parses
+const BASE64URL_PREFIX: char = 'u';
+const BASE58_PREFIX: char = 'z';
+const BASE64URL_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+// bitcoin alphabet, no 0, O, I or l so it survives being read out loud
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Text forms encoded bytes can be written in.
+/// Everything except hex gets a one character prefix (same as multibase),
+/// so [`text_to_bytes`] can tell them apart
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum TextEncoding {
+    /// 2 characters per byte, no prefix
+    #[default]
+    Hex,
+    /// 4 characters per 3 bytes, no padding, prefixed with `u`. Safe in urls and query strings
+    Base64Url,
+    /// about 1.37 characters per byte, prefixed with `z`. No punctuation and no look alike characters
+    Base58,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 3] = [TextEncoding::Hex, TextEncoding::Base64Url, TextEncoding::Base58];
+}
+
+impl Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Writes the bytes as text, prefixed so the encoding can be detected again
+pub fn bytes_to_text(bytes: &[u8], encoding: TextEncoding) -> String {
+    match encoding {
+        TextEncoding::Hex => hex::encode(bytes),
+        TextEncoding::Base64Url => format!("{BASE64URL_PREFIX}{}", base64url_encode(bytes)),
+        TextEncoding::Base58 => format!("{BASE58_PREFIX}{}", base58_encode(bytes)),
+    }
+}
+
+/// Reads text written by [`bytes_to_text`], detecting the encoding from the prefix.
+/// Surrounding whitespace is ignored. Returns None for invalid characters or lengths
+pub fn text_to_bytes(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix(BASE64URL_PREFIX) {
+        base64url_decode(rest)
+    } else if let Some(rest) = text.strip_prefix(BASE58_PREFIX) {
+        base58_decode(rest)
+    } else {
+        hex::decode(text).ok()
+    }
+}
+
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, b)| group | (*b as u32) << (16 - 8 * i));
+        // a chunk of n bytes takes n + 1 characters
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64url_decode(text: &str) -> Option<Vec<u8>> {
+    // a single character left over can't hold a whole byte
+    if text.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        let mut group = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64URL_ALPHABET.iter().position(|a| a == c)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn base58_encode(bytes: &[u8]) -> String {
+    // leading zero bytes are written as leading '1's, since they'd vanish from the number
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+
+    // base 58 digits, least significant first
+    let mut digits: Vec<u8> = vec![];
+    for byte in &bytes[zeros..] {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    std::iter::repeat_n(BASE58_ALPHABET[0] as char, zeros)
+        .chain(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize] as char))
+        .collect()
+}
+
+fn base58_decode(text: &str) -> Option<Vec<u8>> {
+    let zeros = text.bytes().take_while(|c| *c == BASE58_ALPHABET[0]).count();
+
+    // bytes, least significant first
+    let mut bytes: Vec<u8> = vec![];
+    for c in text.bytes().skip(zeros) {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    Some(std::iter::repeat_n(0, zeros).chain(bytes.into_iter().rev()).collect())
+}
+
+impl OneByteRle {
+    /// Writes the bytes as text in the given encoding. `to_string` always gives hex
+    pub fn to_text(&self, encoding: TextEncoding) -> String {
+        bytes_to_text(&self.bytes, encoding)
+    }
+}
+
+/// Hex, like every asset string from before the other text encodings
+impl Display for OneByteRle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", bytes_to_text(&self.bytes, TextEncoding::Hex))
+    }
+}
+
+/// Why a string couldn't be parsed into a [`OneByteRle`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParseRleError {
+    /// not valid hex, base64url or base58
+    InvalidText,
+    /// the format prefix is cut short or names a format that doesn't exist
+    InvalidFormat,
+    /// decoded to nothing, so there is no header
+    MissingHeader,
+}
+
+impl Display for ParseRleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseRleError::InvalidText => write!(f, "text is not valid hex, base64url or base58"),
+            ParseRleError::InvalidFormat => write!(f, "encoding has an invalid format prefix"),
+            ParseRleError::MissingHeader => write!(f, "encoding has no header byte"),
+        }
+    }
+}
+
+impl std::error::Error for ParseRleError {}
+
+/// Parses any of the [`TextEncoding`]s. The format comes from the format prefix, like any other bytes
+impl FromStr for OneByteRle {
+    type Err = ParseRleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = text_to_bytes(s).ok_or(ParseRleError::InvalidText)?;
+        split_format(&bytes).ok_or(ParseRleError::InvalidFormat)?;
+        OneByteRle::new_with_bytes(bytes).ok_or(ParseRleError::MissingHeader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::onebyte_rle::tests::SAMPLE;
+
+    #[test]
+    fn round_trips_every_encoding() {
+        let cases: [&[u8]; 5] = [&[], &[0], &[0, 0, 1], &[0xFF; 7], &hex::decode(SAMPLE).unwrap()];
+        for bytes in cases {
+            for encoding in TextEncoding::ALL {
+                assert_eq!(text_to_bytes(&bytes_to_text(bytes, encoding)).as_deref(), Some(bytes), "{encoding}");
+            }
+        }
+    }
+
+    #[test]
+    fn readme_character_counts() {
+        let rle: OneByteRle = SAMPLE.parse().unwrap();
+        let lengths = TextEncoding::ALL.map(|encoding| rle.to_text(encoding).len());
+        assert_eq!(lengths, [106, 72, 74]);
+        assert_eq!(rle.to_string(), SAMPLE);
+        for encoding in TextEncoding::ALL {
+            assert_eq!(rle.to_text(encoding).parse::<OneByteRle>().unwrap().bytes, rle.bytes);
+        }
+    }
+
+    #[test]
+    fn ignores_surrounding_whitespace() {
+        assert_eq!(text_to_bytes("  4812\n"), Some(vec![0x48, 0x12]));
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        for text in ["48g2", "481", "uA", "u48=!", "z0OIl"] {
+            assert_eq!(text_to_bytes(text), None, "{text}");
+            assert_eq!(text.parse::<OneByteRle>().err(), Some(ParseRleError::InvalidText));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_streams() {
+        assert_eq!("".parse::<OneByteRle>().err(), Some(ParseRleError::MissingHeader));
+        assert_eq!("fe".parse::<OneByteRle>().err(), Some(ParseRleError::InvalidFormat));
+    }
+}
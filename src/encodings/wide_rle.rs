@@ -0,0 +1,168 @@
+use bevy::log::{info, warn};
+use crate::types::{IndexedImage, WideColorIndex};
+use super::{OneByteRle, RunByte};
+
+const OFFSET_LIMIT: u8 = 0x1 << 3;
+
+/// Image encoded with 4 bit colors, for assets that need more than 7 colors.
+/// Same header byte as [`OneByteRle`] (3 bit left offset, 5 bit width - 1),
+/// then one byte per run: color index in the 4 most significant bits and run length - 1 in the other 4,
+/// so runs hold up to 16 pixels.
+/// None of the [`super::RleFormat`] extensions apply to it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WideRle {
+    pub header_offset: u8,
+    pub header_width: u8,
+    pub bytes: Vec<u8>,
+}
+
+impl WideRle {
+    /// returns None if bytes are empty
+    pub fn new_with_bytes(bytes: Vec<u8>) -> Option<Self> {
+        let (header_offset, header_width) = OneByteRle::get_header_from_byte(*bytes.first()?);
+        Some(Self {
+            header_offset,
+            header_width,
+            bytes,
+        })
+    }
+
+    fn push_run(&mut self, color: WideColorIndex, mut length: usize) {
+        while length > 0 {
+            let run = usize::min(length, RunByte::<WideColorIndex>::RUN_LENGTH_LIMIT as usize);
+            self.bytes.push(RunByte::new(color, run as u8).get());
+            length -= run;
+        }
+    }
+}
+
+/// Same steps as [`super::indexed_to_rle`]: the window starts at the left most pixel
+/// (offset capped at 7) and the image's vertical trim, and trailing empty pixels are dropped
+pub fn indexed_to_wide_rle<const PIXELS: usize, const WIDTH: usize>(image: &IndexedImage<PIXELS, WIDTH, WideColorIndex>) -> WideRle {
+    let trim = image.vertical_trim;
+
+    // if canvas was empty we set min and max to 0
+    let (min_x, max_x) = image.enumerate_pixels()
+        .filter(|(_, y, p)| *y >= trim && **p != WideColorIndex::EMPTY)
+        .fold(None, |columns: Option<(u8, u8)>, (x, _, _)| Some(match columns {
+            Some((min_x, max_x)) => (u8::min(min_x, x), u8::max(max_x, x)),
+            None => (x, x),
+        }))
+        .unwrap_or_default();
+    let offset = u8::min(min_x, OFFSET_LIMIT - 1);
+    let encode_width = max_x - offset;
+    debug_assert!(encode_width < 0x1 << 5);
+
+    let mut out = WideRle {
+        header_offset: offset,
+        header_width: encode_width,
+        bytes: vec![offset << 5 | encode_width],
+    };
+
+    // runs of the pixels inside the window
+    let mut runs: Vec<(WideColorIndex, usize)> = vec![];
+    for (x, y, p) in image.enumerate_pixels() {
+        if y < trim || x < offset || x > offset + encode_width { continue; }
+        match runs.last_mut() {
+            Some((color, length)) if color == p => *length += 1,
+            _ => runs.push((*p, 1)),
+        }
+    }
+    while let Some((WideColorIndex::EMPTY, _)) = runs.last() {
+        runs.pop();
+    }
+
+    for (color, length) in runs {
+        out.push_run(color, length);
+    }
+    info!("wide encoded to {} bytes", out.bytes.len());
+    out
+}
+
+/// From y = trim and x = offset, writes pixels from left to right, wrapping at the header width.
+/// Pixels outside of the image are dropped
+pub fn wide_rle_to_indexed<const PIXELS: usize, const WIDTH: usize>(rle: &WideRle, trim: u8) -> IndexedImage<PIXELS, WIDTH, WideColorIndex> {
+    let mut out = IndexedImage::new();
+    let Some((header, runs)) = rle.bytes.split_first() else {
+        warn!("ENCOUNTERED INVALID RLE HEADER");
+        return out;
+    };
+    let (offset, encode_width) = OneByteRle::get_header_from_byte(*header);
+    let width = encode_width as usize + 1;
+
+    let mut position = 0;
+    for byte in runs {
+        // every nibble is a valid color, so this never stops early
+        let Some(run) = RunByte::<WideColorIndex>::from_byte(*byte) else { break };
+        let color = run.color();
+        for _ in 0..run.run_length() {
+            let x = offset as usize + position % width;
+            let y = trim as usize + position / width;
+            if x < WIDTH && y < PIXELS / WIDTH {
+                out[(x, y)] = color;
+            }
+            position += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(index: u8) -> WideColorIndex {
+        WideColorIndex::new(index).unwrap()
+    }
+
+    /// every index in diagonal stripes, then a 40 pixel run of one color across rows 8 to 10
+    fn all_colors() -> IndexedImage<256, 16, WideColorIndex> {
+        let mut image = IndexedImage::new();
+        for y in 0..8 {
+            for x in 0..16 {
+                image[(x, y)] = wide(((x + y) % 16) as u8);
+            }
+        }
+        for i in 0..40 {
+            image[(i % 16, 8 + i / 16)] = wide(15);
+        }
+        image
+    }
+
+    // wide images have no ascii Debug, so they're compared with assert!
+    #[test]
+    fn round_trips_every_index() {
+        let image = all_colors();
+        let rle = indexed_to_wide_rle(&image);
+        assert!(wide_rle_to_indexed::<256, 16>(&rle, 0) == image);
+        // the 40 pixel run needs 3 run bytes
+        let long_runs = rle.bytes.iter().filter(|b| **b == 0xFF).count();
+        assert_eq!(long_runs, 2);
+        assert_eq!(WideRle::new_with_bytes(rle.bytes.clone()), Some(rle));
+    }
+
+    #[test]
+    fn round_trips_with_trim_and_offset() {
+        let mut image: IndexedImage<256, 16, WideColorIndex> = IndexedImage::new();
+        for x in 9..13 {
+            for y in 4..12 {
+                image[(x, y)] = wide(((x + y) % 15 + 1) as u8);
+            }
+        }
+        image.vertical_trim = 4;
+        let rle = indexed_to_wide_rle(&image);
+        assert_eq!((rle.header_offset, rle.header_width), (7, 5));
+        let mut decoded = wide_rle_to_indexed::<256, 16>(&rle, 4);
+        decoded.vertical_trim = 4;
+        assert!(decoded == image);
+    }
+
+    #[test]
+    fn empty_image() {
+        let image: IndexedImage<256, 16, WideColorIndex> = IndexedImage::new();
+        let rle = indexed_to_wide_rle(&image);
+        assert_eq!(rle.bytes, [0]);
+        assert!(wide_rle_to_indexed::<256, 16>(&rle, 0) == image);
+        assert_eq!(WideRle::new_with_bytes(vec![]), None);
+    }
+}
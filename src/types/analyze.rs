@@ -0,0 +1,277 @@
+use std::fmt;
+use super::{contrast_ratio, linear_to_srgb, srgb_to_linear, ColorIndex, Oklab, Palette, PaletteCollection};
+
+/// How a palette looks to people with different kinds of color vision
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ColorVision {
+    #[default]
+    Normal,
+    /// no red cones
+    Protanopia,
+    /// no green cones
+    Deuteranopia,
+    /// no blue cones
+    Tritanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Normal,
+        ColorVision::Protanopia,
+        ColorVision::Deuteranopia,
+        ColorVision::Tritanopia,
+    ];
+
+    /// linear RGB matrices for full severity, from Machado, Oliveira and Fernandes (2009)
+    fn matrix(&self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            ColorVision::Normal => None,
+            ColorVision::Protanopia => Some([
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ]),
+            ColorVision::Deuteranopia => Some([
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ]),
+            ColorVision::Tritanopia => Some([
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ]),
+        }
+    }
+
+    /// the color as this kind of vision sees it, alpha is kept
+    pub fn simulate(&self, color: &image::Rgba<u8>) -> image::Rgba<u8> {
+        let Some(matrix) = self.matrix() else {
+            return *color;
+        };
+        let linear = [color.0[0], color.0[1], color.0[2]].map(srgb_to_linear);
+        let [r, g, b] = matrix.map(|row| linear_to_srgb(row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]));
+        image::Rgba([r, g, b, color.0[3]])
+    }
+}
+
+impl fmt::Display for ColorVision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorVision::Normal => write!(f, "normal vision"),
+            ColorVision::Protanopia => write!(f, "protanopia"),
+            ColorVision::Deuteranopia => write!(f, "deuteranopia"),
+            ColorVision::Tritanopia => write!(f, "tritanopia"),
+        }
+    }
+}
+
+/// Color roles that usually touch in a sprite: the Dark outline against everything,
+/// plus skin, clothes and eyes against each other
+pub const ADJACENT_ROLES: [(ColorIndex, ColorIndex); 11] = [
+    (ColorIndex::Dark, ColorIndex::Bright),
+    (ColorIndex::Dark, ColorIndex::Skin),
+    (ColorIndex::Dark, ColorIndex::ShirtAccent1),
+    (ColorIndex::Dark, ColorIndex::PantsAccent2),
+    (ColorIndex::Dark, ColorIndex::EyesAccent3),
+    (ColorIndex::Dark, ColorIndex::Accent4),
+    (ColorIndex::Bright, ColorIndex::EyesAccent3),
+    (ColorIndex::Skin, ColorIndex::ShirtAccent1),
+    (ColorIndex::Skin, ColorIndex::EyesAccent3),
+    (ColorIndex::ShirtAccent1, ColorIndex::PantsAccent2),
+    (ColorIndex::ShirtAccent1, ColorIndex::Accent4),
+];
+
+/// Limits a palette has to meet to pass [`Palette::analyze`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteThresholds {
+    /// smallest Oklab distance (x100, see [`Oklab::distance`]) allowed between any two colors, under every kind of vision
+    pub min_distance: f32,
+    /// smallest WCAG contrast ratio allowed between adjacent colors
+    pub min_contrast: f32,
+    /// color index pairs that get the contrast check, pairs past the end of the palette are skipped
+    pub adjacent: Vec<(u8, u8)>,
+}
+
+impl Default for PaletteThresholds {
+    fn default() -> Self {
+        Self {
+            min_distance: 5.0,
+            min_contrast: 1.5,
+            adjacent: ADJACENT_ROLES.iter().map(|(a, b)| (*a as u8, *b as u8)).collect(),
+        }
+    }
+}
+
+/// The two most similar colors of a palette, by color index
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClosestPair {
+    pub a: u8,
+    pub b: u8,
+    pub distance: f32,
+}
+
+/// WCAG contrast between two color indices
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PairContrast {
+    pub a: u8,
+    pub b: u8,
+    pub ratio: f32,
+}
+
+/// Something that makes a palette fail its thresholds
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PaletteIssue {
+    /// two colors are closer than `min_distance` under this kind of vision
+    TooSimilar { vision: ColorVision, pair: ClosestPair },
+    /// two adjacent colors have less contrast than `min_contrast`
+    LowContrast(PairContrast),
+}
+
+impl fmt::Display for PaletteIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteIssue::TooSimilar { vision, pair } => write!(
+                f, "colors {} and {} are too similar with {vision} (distance {:.1})", pair.a, pair.b, pair.distance
+            ),
+            PaletteIssue::LowContrast(pair) => write!(
+                f, "colors {} and {} have low contrast ({:.2}:1)", pair.a, pair.b, pair.ratio
+            ),
+        }
+    }
+}
+
+/// What [`Palette::analyze`] found
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteReport {
+    /// closest pair of colors for every [`ColorVision`], in [`ColorVision::ALL`] order.
+    /// None for palettes with less than 2 colors
+    pub closest: Vec<(ColorVision, Option<ClosestPair>)>,
+    /// contrast of every adjacent pair that's in the palette
+    pub contrast: Vec<PairContrast>,
+    pub issues: Vec<PaletteIssue>,
+}
+
+impl PaletteReport {
+    pub fn passes(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// the closest pair with normal vision
+    pub fn min_distance(&self) -> Option<ClosestPair> {
+        self.closest.first().and_then(|(_, pair)| *pair)
+    }
+}
+
+/// closest pair of colors, indices are palette positions + 1 (the color index)
+fn closest_pair(colors: &[Oklab]) -> Option<ClosestPair> {
+    let mut closest: Option<ClosestPair> = None;
+    for (i, a) in colors.iter().enumerate() {
+        for (j, b) in colors.iter().enumerate().skip(i + 1) {
+            let distance = a.distance(b);
+            match closest {
+                Some(pair) if pair.distance <= distance => {},
+                _ => closest = Some(ClosestPair { a: i as u8 + 1, b: j as u8 + 1, distance }),
+            }
+        }
+    }
+    closest
+}
+
+impl<const C: usize> Palette<u8, C> {
+    /// Checks how distinct the colors are (with every kind of [`ColorVision`])
+    /// and how much the adjacent ones contrast. Alpha is ignored
+    pub fn analyze(&self, thresholds: &PaletteThresholds) -> PaletteReport {
+        let mut issues = vec![];
+
+        let closest: Vec<_> = ColorVision::ALL.into_iter().map(|vision| {
+            let colors: Vec<_> = self.colors().iter().map(|color| Oklab::from_rgba(&vision.simulate(color))).collect();
+            (vision, closest_pair(&colors))
+        }).collect();
+        for (vision, pair) in &closest {
+            if let Some(pair) = pair.filter(|pair| pair.distance < thresholds.min_distance) {
+                issues.push(PaletteIssue::TooSimilar { vision: *vision, pair });
+            }
+        }
+
+        let contrast: Vec<_> = thresholds.adjacent.iter()
+            .filter_map(|&(a, b)| {
+                let ratio = contrast_ratio(self.colors().get(a.checked_sub(1)? as usize)?, self.colors().get(b.checked_sub(1)? as usize)?);
+                Some(PairContrast { a, b, ratio })
+            })
+            .collect();
+        issues.extend(contrast.iter()
+            .filter(|pair| pair.ratio < thresholds.min_contrast)
+            .map(|pair| PaletteIssue::LowContrast(*pair)));
+
+        PaletteReport {
+            closest,
+            contrast,
+            issues,
+        }
+    }
+}
+
+impl<const C: usize> PaletteCollection<u8, C> {
+    /// a report for each of the 8 palettes
+    pub fn analyze(&self, thresholds: &PaletteThresholds) -> Vec<PaletteReport> {
+        self.iter().map(|palette| palette.analyze(thresholds)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
+    const WHITE: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn contrast_extremes() {
+        assert!((contrast_ratio(&BLACK, &WHITE) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(&WHITE, &BLACK) - 21.0).abs() < 0.01);
+        let skin = image::Rgba([204, 164, 153, 255]);
+        assert_eq!(contrast_ratio(&skin, &skin), 1.0);
+    }
+
+    #[test]
+    fn normal_vision_is_unchanged() {
+        for value in 0..=255u8 {
+            let color = image::Rgba([value, 255 - value, value / 2, value]);
+            assert_eq!(ColorVision::Normal.simulate(&color), color);
+        }
+        // the others keep alpha and leave greys grey
+        for vision in ColorVision::ALL {
+            let grey = vision.simulate(&image::Rgba([128, 128, 128, 7]));
+            assert_eq!(grey.0[3], 7);
+            assert!(grey.0[..3].iter().all(|c| c.abs_diff(128) <= 1), "{vision} {grey:?}");
+        }
+    }
+
+    #[test]
+    fn readme_default_palette_report() {
+        let report = Palette::<u8>::default().analyze(&PaletteThresholds::default());
+        let dark = ColorIndex::Dark as u8;
+        let accent4 = ColorIndex::Accent4 as u8;
+        let pants = ColorIndex::PantsAccent2 as u8;
+        let eyes = ColorIndex::EyesAccent3 as u8;
+
+        // Dark against Accent4 at 1.35:1 and Skin against ShirtAccent1 at 1.16:1
+        let low: Vec<_> = report.issues.iter().filter_map(|issue| match issue {
+            PaletteIssue::LowContrast(pair) => Some((pair.a, pair.b, format!("{:.2}", pair.ratio))),
+            _ => None,
+        }).collect();
+        assert_eq!(low, [
+            (dark, accent4, "1.35".to_string()),
+            (ColorIndex::Skin as u8, ColorIndex::ShirtAccent1 as u8, "1.16".to_string()),
+        ]);
+
+        // PantsAccent2 against EyesAccent3 with protanopia
+        let similar: Vec<_> = report.issues.iter().filter_map(|issue| match issue {
+            PaletteIssue::TooSimilar { vision, pair } => Some((*vision, pair.a, pair.b)),
+            _ => None,
+        }).collect();
+        assert_eq!(similar, [(ColorVision::Protanopia, pants, eyes)]);
+        assert!(!report.passes());
+    }
+}
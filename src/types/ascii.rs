@@ -0,0 +1,133 @@
+use std::{fmt, str::FromStr};
+use bytemuck::Contiguous;
+use super::{ColorIndex, IndexedImage};
+
+impl ColorIndex {
+    /// Character used for this color in the ascii grid format:
+    /// `.` for Empty, `#` for Dark, and the index digit for the rest
+    pub fn to_ascii(&self) -> char {
+        match self {
+            ColorIndex::Empty => '.',
+            ColorIndex::Dark => '#',
+            other => char::from(b'0' + *other as u8),
+        }
+    }
+
+    /// Reads a grid character. `0` and `1` are accepted for Empty and Dark too
+    pub fn from_ascii(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(ColorIndex::Empty),
+            '#' => Some(ColorIndex::Dark),
+            _ => ColorIndex::from_integer(c.to_digit(10)? as u8),
+        }
+    }
+}
+
+/// Draws the image as an ascii grid, one line per row. Ex. a 4x2 image:
+/// ```text
+/// .##.
+/// #23#
+/// ```
+impl<const N: usize, const W: usize> fmt::Debug for IndexedImage<N, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (x, y, p) in self.enumerate_pixels() {
+            if x == 0 && y > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", p.to_ascii())?;
+        }
+        Ok(())
+    }
+}
+
+/// Why an ascii grid couldn't be parsed into an [`IndexedImage`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParseImageError {
+    /// the character at (x, y) isn't a grid character
+    InvalidPixel { x: usize, y: usize, c: char },
+    /// the row doesn't have as many pixels as the image is wide
+    RowWidth { y: usize, width: usize },
+    /// the grid doesn't have as many rows as the image is tall
+    RowCount(usize),
+}
+
+impl fmt::Display for ParseImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseImageError::InvalidPixel { x, y, c } => write!(f, "invalid pixel '{c}' at ({x}, {y})"),
+            ParseImageError::RowWidth { y, width } => write!(f, "row {y} is {width} pixels wide"),
+            ParseImageError::RowCount(rows) => write!(f, "grid has {rows} rows"),
+        }
+    }
+}
+
+impl std::error::Error for ParseImageError {}
+
+/// Reads the grid written by `Debug`. Blank lines and whitespace around each row are skipped,
+/// so grids can be indented inside raw strings
+impl<const N: usize, const W: usize> FromStr for IndexedImage<N, W> {
+    type Err = ParseImageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut image = IndexedImage::new();
+        let rows: Vec<&str> = s.lines().map(str::trim).filter(|row| !row.is_empty()).collect();
+        if rows.len() != N / W {
+            return Err(ParseImageError::RowCount(rows.len()));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            let width = row.chars().count();
+            if width != W {
+                return Err(ParseImageError::RowWidth { y, width });
+            }
+            for (x, c) in row.chars().enumerate() {
+                image[(x, y)] = ColorIndex::from_ascii(c).ok_or(ParseImageError::InvalidPixel { x, y, c })?;
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::fixtures::sample;
+
+    /// the grid from the readme
+    const GRID: &str = "...##333##......\n...3223223......\n...3263623......";
+
+    #[test]
+    fn readme_grid_round_trips() {
+        let image: IndexedImage<48, 16> = GRID.parse().unwrap();
+        assert_eq!(image[(3, 0)], ColorIndex::Dark);
+        assert_eq!(image[(5, 2)], ColorIndex::EyesAccent3);
+        assert_eq!(format!("{image:?}"), GRID);
+        assert_eq!(format!("{image:?}").parse::<IndexedImage<48, 16>>(), Ok(image));
+    }
+
+    #[test]
+    fn sample_round_trips() {
+        let image = sample();
+        assert_eq!(format!("{image:?}").parse::<IndexedImage<256, 16>>(), Ok(image));
+    }
+
+    #[test]
+    fn skips_indentation_and_blank_lines() {
+        let indented = "
+            ...##333##......
+
+            ...3223223......
+            ...3263623......
+        ";
+        assert_eq!(indented.parse::<IndexedImage<48, 16>>(), GRID.parse());
+        // 0 and 1 are Empty and Dark too
+        assert_eq!("0110\n1231".parse::<IndexedImage<8, 4>>(), ".##.\n#23#".parse());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("....\n....\n....".parse::<IndexedImage<8, 4>>(), Err(ParseImageError::RowCount(3)));
+        assert_eq!("....\n...".parse::<IndexedImage<8, 4>>(), Err(ParseImageError::RowWidth { y: 1, width: 3 }));
+        assert_eq!("....\n..8.".parse::<IndexedImage<8, 4>>(), Err(ParseImageError::InvalidPixel { x: 2, y: 1, c: '8' }));
+        assert_eq!("x...\n....".parse::<IndexedImage<8, 4>>(), Err(ParseImageError::InvalidPixel { x: 0, y: 0, c: 'x' }));
+    }
+}
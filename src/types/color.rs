@@ -0,0 +1,187 @@
+/// A color as hue (degrees, 0 to 360), saturation and lightness (both 0 to 1).
+/// Used by palette tools, palettes themselves are always stored as RGBA
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+impl Hsl {
+    /// wraps the hue and clamps saturation and lightness
+    pub fn new(h: f32, s: f32, l: f32) -> Self {
+        Self {
+            h: h.rem_euclid(360.0),
+            s: s.clamp(0.0, 1.0),
+            l: l.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn from_rgba(color: &image::Rgba<u8>) -> Self {
+        let [r, g, b] = [color.0[0], color.0[1], color.0[2]].map(|c| c as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        // greys have no hue
+        if delta == 0.0 {
+            return Self { h: 0.0, s: 0.0, l };
+        }
+        let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / delta)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        Self::new(h, s, l)
+    }
+
+    pub fn to_rgba(&self, alpha: u8) -> image::Rgba<u8> {
+        let chroma = (1.0 - (2.0 * self.l - 1.0).abs()) * self.s;
+        let sector = self.h / 60.0;
+        let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = self.l - chroma / 2.0;
+        let [r, g, b] = [r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8);
+        image::Rgba([r, g, b, alpha])
+    }
+
+    /// Moves the hue up to `degrees` toward `target` the short way around, without passing it
+    pub fn shift_hue_toward(&self, target: f32, degrees: f32) -> Self {
+        let difference = (target - self.h + 540.0).rem_euclid(360.0) - 180.0;
+        let step = difference.clamp(-degrees.abs(), degrees.abs());
+        Self::new(self.h + step, self.s, self.l)
+    }
+}
+
+/// sRGB channel (0 to 255) to linear light (0 to 1)
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// linear light (0 to 1) to a sRGB channel, clamped
+pub fn linear_to_srgb(linear: f32) -> u8 {
+    let c = linear.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// WCAG relative luminance, 0 for black and 1 for white. Alpha is ignored
+pub fn relative_luminance(color: &image::Rgba<u8>) -> f32 {
+    let [r, g, b] = [color.0[0], color.0[1], color.0[2]].map(srgb_to_linear);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// WCAG contrast ratio, from 1 (same luminance) to 21 (black on white)
+pub fn contrast_ratio(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (f32::max(a, b) + 0.05) / (f32::min(a, b) + 0.05)
+}
+
+/// A color in the Oklab perceptual space: lightness `l` (0 to 1) and the `a` (green/red) and `b` (blue/yellow) axes.
+/// Equal distances look about equally different anywhere in the space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Oklab {
+    pub fn from_rgba(color: &image::Rgba<u8>) -> Self {
+        let [r, g, b] = [color.0[0], color.0[1], color.0[2]].map(srgb_to_linear);
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+
+    /// colors outside of sRGB are clamped per channel, see [`Self::fit_gamut`] for keeping the hue instead
+    pub fn to_rgba(&self, alpha: u8) -> image::Rgba<u8> {
+        let [r, g, b] = self.linear_rgb().map(linear_to_srgb);
+        image::Rgba([r, g, b, alpha])
+    }
+
+    fn linear_rgb(&self) -> [f32; 3] {
+        let l = (self.l + 0.396_337_78 * self.a + 0.215_803_76 * self.b).powi(3);
+        let m = (self.l - 0.105_561_346 * self.a - 0.063_854_17 * self.b).powi(3);
+        let s = (self.l - 0.089_484_18 * self.a - 1.291_485_5 * self.b).powi(3);
+        [
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        ]
+    }
+
+    /// true if the color fits in sRGB (with a little slack for rounding)
+    pub fn in_gamut(&self) -> bool {
+        self.linear_rgb().iter().all(|c| (-0.001..=1.001).contains(c))
+    }
+
+    /// Lowers chroma until the color fits in sRGB, keeping its lightness and hue
+    pub fn fit_gamut(&self) -> Self {
+        let l = self.l.clamp(0.0, 1.0);
+        let scaled = |t: f32| Self { l, a: self.a * t, b: self.b * t };
+        if scaled(1.0).in_gamut() {
+            return scaled(1.0);
+        }
+        // greys always fit, so search between no chroma and all of it
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..16 {
+            let middle = (low + high) / 2.0;
+            if scaled(middle).in_gamut() {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        scaled(low)
+    }
+
+    /// Turns the hue around the lightness axis, keeping lightness and chroma
+    pub fn rotate_hue(&self, degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self {
+            l: self.l,
+            a: self.a * cos - self.b * sin,
+            b: self.a * sin + self.b * cos,
+        }
+    }
+
+    /// `t` of 0 is `self` and 1 is `other`
+    pub fn lerp(&self, other: &Oklab, t: f32) -> Self {
+        Self {
+            l: self.l + (other.l - self.l) * t,
+            a: self.a + (other.a - self.a) * t,
+            b: self.b + (other.b - self.b) * t,
+        }
+    }
+
+    /// Distance between two colors scaled by 100, around 2 is barely noticeable side by side
+    pub fn distance(&self, other: &Oklab) -> f32 {
+        let (l, a, b) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        (l * l + a * a + b * b).sqrt() * 100.0
+    }
+}
@@ -0,0 +1,249 @@
+use bevy::log::warn;
+use super::{relative_luminance, ColorIndex, IndexedImage, IndexedPixel, Oklab, Palette};
+
+/// pixels with less alpha than this become Empty
+const ALPHA_CUTOFF: u8 = 128;
+
+/// How [`extract_palette`] picks colors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Quantizer {
+    /// keeps splitting the group of pixels with the widest color range at its median
+    MedianCut,
+    /// starts from the median cut colors and moves them to the middle of the pixels closest to them,
+    /// up to `iterations` times (stops early once nothing moves)
+    KMeans { iterations: usize },
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Quantizer::KMeans { iterations: 16 }
+    }
+}
+
+/// A palette taken from an image, along with the image drawn in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedPalette<const N: usize, const W: usize> {
+    pub palette: Palette<u8>,
+    pub image: IndexedImage<N, W>,
+}
+
+/// Picks up to 7 colors for an image and maps every pixel to the closest one (Oklab distance).
+///
+/// - pixels with alpha under 128 are Empty, colors get the mean alpha of their pixels
+/// - the darkest color goes to Dark and the lightest to Bright,
+///   the rest go from lightest to darkest to Skin, ShirtAccent1 and so on
+/// - images with less than 7 colors keep the default palette colors for the unused indices
+///
+/// The image is read from its top left corner, pixels past the `IndexedImage` size are dropped.
+/// Returns None (and warns) if the image has no opaque pixels
+pub fn extract_palette<const N: usize, const W: usize>(image: &image::RgbaImage, quantizer: Quantizer) -> Option<ExtractedPalette<N, W>> {
+    let height = N / W;
+    if image.width() as usize != W || image.height() as usize != height {
+        warn!("image is {}x{}, extracting the top left {W}x{height}", image.width(), image.height());
+    }
+    let pixels: Vec<(usize, usize, &image::Rgba<u8>)> = image.enumerate_pixels()
+        .map(|(x, y, p)| (x as usize, y as usize, p))
+        .filter(|(x, y, p)| *x < W && *y < height && p.0[3] >= ALPHA_CUTOFF)
+        .collect();
+    if pixels.is_empty() {
+        warn!("NO OPAQUE PIXELS TO EXTRACT A PALETTE FROM");
+        return None;
+    }
+
+    let samples: Vec<[f32; 3]> = pixels.iter().map(|(_, _, p)| to_array(Oklab::from_rgba(p))).collect();
+    let mut centers = median_cut(&samples, ColorIndex::COLORS);
+    if let Quantizer::KMeans { iterations } = quantizer {
+        centers = k_means(&samples, centers, iterations);
+    }
+
+    // mean alpha of every center's pixels
+    let assigned: Vec<usize> = samples.iter().map(|sample| nearest(&centers, sample)).collect();
+    let colors: Vec<image::Rgba<u8>> = centers.iter().enumerate().map(|(i, center)| {
+        let alphas: Vec<u32> = pixels.iter().zip(&assigned)
+            .filter(|(_, a)| **a == i)
+            .map(|((_, _, p), _)| p.0[3] as u32)
+            .collect();
+        let alpha = alphas.iter().sum::<u32>() / alphas.len().max(1) as u32;
+        from_array(*center).to_rgba(alpha as u8)
+    }).collect();
+
+    // palette position of every center, ordered by luminance
+    let mut order: Vec<usize> = (0..colors.len()).collect();
+    order.sort_by(|a, b| relative_luminance(&colors[*a]).total_cmp(&relative_luminance(&colors[*b])));
+    let darkest = order.remove(0);
+    let mut roles = vec![darkest];
+    roles.extend(order.into_iter().rev());
+
+    let mut palette = Palette::default();
+    let mut index_of = vec![ColorIndex::Empty; colors.len()];
+    for (position, center) in roles.iter().enumerate() {
+        // only 7 centers, so every position is a color
+        let color = ColorIndex::from_u8(position as u8 + 1).unwrap_or_default();
+        palette[color] = colors[*center];
+        index_of[*center] = color;
+    }
+
+    let mut out = IndexedImage::new();
+    for ((x, y, _), center) in pixels.iter().zip(&assigned) {
+        out[(*x, *y)] = index_of[*center];
+    }
+    Some(ExtractedPalette { palette, image: out })
+}
+
+fn to_array(color: Oklab) -> [f32; 3] {
+    [color.l, color.a, color.b]
+}
+
+fn from_array([l, a, b]: [f32; 3]) -> Oklab {
+    Oklab { l, a, b }
+}
+
+fn distance_squared(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+fn nearest(centers: &[[f32; 3]], sample: &[f32; 3]) -> usize {
+    centers.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance_squared(a, sample).total_cmp(&distance_squared(b, sample)))
+        .map_or(0, |(i, _)| i)
+}
+
+fn mean(samples: &[[f32; 3]]) -> [f32; 3] {
+    let mut sum = [0.0; 3];
+    for sample in samples {
+        for (total, value) in sum.iter_mut().zip(sample) {
+            *total += value;
+        }
+    }
+    sum.map(|s| s / samples.len().max(1) as f32)
+}
+
+/// axis with the widest range, and that range
+fn widest_axis(samples: &[[f32; 3]]) -> (usize, f32) {
+    (0..3)
+        .map(|axis| {
+            let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), s| (min.min(s[axis]), max.max(s[axis])));
+            (axis, max - min)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or((0, 0.0))
+}
+
+/// Up to `colors` centers, less if the samples don't have that many distinct colors
+fn median_cut(samples: &[[f32; 3]], colors: usize) -> Vec<[f32; 3]> {
+    let mut boxes = vec![samples.to_vec()];
+    while boxes.len() < colors {
+        // split the box with the widest range, boxes of one color can't be split
+        let Some((index, axis)) = boxes.iter()
+            .enumerate()
+            .map(|(i, b)| (i, widest_axis(b)))
+            .filter(|(_, (_, range))| *range > 0.0)
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))
+            .map(|(i, (axis, _))| (i, axis))
+        else { break };
+        let mut split = boxes.swap_remove(index);
+        split.sort_by(|a, b| a[axis].total_cmp(&b[axis]));
+        // cut between different values, so both halves keep at least one sample
+        let middle = split.len() / 2;
+        let cut = (middle..split.len())
+            .find(|i| split[*i][axis] > split[i - 1][axis])
+            .or_else(|| (1..middle).rev().find(|i| split[*i][axis] > split[i - 1][axis]))
+            .unwrap_or(middle);
+        let upper = split.split_off(cut);
+        boxes.push(split);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| mean(b)).collect()
+}
+
+fn k_means(samples: &[[f32; 3]], mut centers: Vec<[f32; 3]>, iterations: usize) -> Vec<[f32; 3]> {
+    for _ in 0..iterations {
+        let mut groups = vec![vec![]; centers.len()];
+        for sample in samples {
+            groups[nearest(&centers, sample)].push(*sample);
+        }
+        // centers without pixels stay where they are
+        let moved: Vec<[f32; 3]> = groups.iter().zip(&centers)
+            .map(|(group, center)| if group.is_empty() { *center } else { mean(group) })
+            .collect();
+        if moved == centers {
+            break;
+        }
+        centers = moved;
+    }
+    centers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::IndexedPixel;
+
+    const DARK: [u8; 4] = [10, 10, 10, 255];
+    const LIGHT: [u8; 4] = [240, 240, 230, 255];
+    const SKIN: [u8; 4] = [200, 150, 130, 255];
+    const BLUE: [u8; 4] = [40, 60, 200, 255];
+
+    /// 8x4 diagonal stripes of 4 colors, with the first 2 pixels transparent
+    fn stripes() -> image::RgbaImage {
+        let colors = [DARK, LIGHT, SKIN, BLUE];
+        image::RgbaImage::from_fn(8, 4, |x, y| match (x, y) {
+            (0..=1, 0) => image::Rgba([0, 0, 0, 0]),
+            _ => image::Rgba(colors[((x + y) % 4) as usize]),
+        })
+    }
+
+    fn gradient() -> image::RgbaImage {
+        image::RgbaImage::from_fn(16, 16, |x, y| image::Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255]))
+    }
+
+    #[test]
+    fn exact_colors_go_to_their_roles() {
+        for quantizer in [Quantizer::MedianCut, Quantizer::default()] {
+            let extracted = extract_palette::<32, 8>(&stripes(), quantizer).unwrap();
+            assert_eq!(extracted.palette[ColorIndex::Dark], image::Rgba(DARK));
+            assert_eq!(extracted.palette[ColorIndex::Bright], image::Rgba(LIGHT));
+            assert_eq!(extracted.palette[ColorIndex::Skin], image::Rgba(SKIN));
+            assert_eq!(extracted.palette[ColorIndex::ShirtAccent1], image::Rgba(BLUE));
+            // unused indices keep the default colors
+            assert_eq!(extracted.palette[ColorIndex::Accent4], Palette::<u8>::default()[ColorIndex::Accent4]);
+            assert_eq!(extracted.image[(0, 0)], ColorIndex::Empty);
+            assert_eq!(extracted.image[(2, 0)], ColorIndex::Skin);
+            assert_eq!(extracted.image[(3, 0)], ColorIndex::ShirtAccent1);
+        }
+    }
+
+    #[test]
+    fn roles_follow_luminance() {
+        for quantizer in [Quantizer::MedianCut, Quantizer::default()] {
+            let extracted = extract_palette::<256, 16>(&gradient(), quantizer).unwrap();
+            let luminance: Vec<f32> = extracted.palette.colors().iter().map(relative_luminance).collect();
+            // Dark is the darkest, then lightest to darkest from Bright on
+            assert!(luminance[1..].iter().all(|l| *l > luminance[0]));
+            assert!(luminance[1..].windows(2).all(|pair| pair[0] >= pair[1]));
+            let used: Vec<_> = (1..=7).filter(|i| extracted.image.enumerate_pixels().any(|(_, _, p)| p.to_u8() == *i)).collect();
+            assert_eq!(used.len(), 7);
+        }
+    }
+
+    #[test]
+    fn alpha_is_the_mean_of_the_pixels() {
+        let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([50, 50, 50, 200 + x as u8 * 50]));
+        let extracted = extract_palette::<2, 2>(&image, Quantizer::MedianCut).unwrap();
+        assert_eq!(extracted.palette[ColorIndex::Dark], image::Rgba([50, 50, 50, 225]));
+        assert_eq!(extracted.image[(1, 0)], ColorIndex::Dark);
+    }
+
+    #[test]
+    fn larger_images_are_cropped() {
+        let extracted = extract_palette::<16, 4>(&stripes(), Quantizer::MedianCut).unwrap();
+        assert_eq!(extracted.image[(2, 0)], ColorIndex::Skin);
+        assert_eq!(extracted.image[(3, 3)], ColorIndex::Skin);
+    }
+
+    #[test]
+    fn transparent_images_have_no_palette() {
+        assert_eq!(extract_palette::<256, 16>(&image::RgbaImage::new(16, 16), Quantizer::MedianCut), None);
+    }
+}
@@ -0,0 +1,191 @@
+use super::{Hsl, Palette, PaletteCollection};
+
+/// hue shadows lean toward (blue)
+const SHADOW_HUE: f32 = 240.0;
+/// hue highlights lean toward (yellow)
+const HIGHLIGHT_HUE: f32 = 60.0;
+
+/// How the accent hues are picked around the base hue
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Harmony {
+    /// neighbouring hues, 30 degrees either side, and 60 degrees out for the last accent
+    Analogous,
+    /// the opposite hue and one either side of it
+    #[default]
+    Complementary,
+    /// hues a third of the way around, and the opposite hue for the last accent
+    Triadic,
+    /// both neighbours of the opposite hue, and the opposite hue itself for the last accent
+    SplitComplementary,
+}
+
+impl Harmony {
+    pub const ALL: [Harmony; 4] = [
+        Harmony::Analogous,
+        Harmony::Complementary,
+        Harmony::Triadic,
+        Harmony::SplitComplementary,
+    ];
+
+    /// the three accent hue offsets from the base hue, in degrees.
+    /// They're all different from each other and from 0, so no two accents share a hue
+    fn offsets(&self) -> (f32, f32, f32) {
+        match self {
+            Harmony::Analogous => (30.0, -30.0, 60.0),
+            Harmony::Complementary => (180.0, 150.0, 210.0),
+            Harmony::Triadic => (120.0, 240.0, 180.0),
+            Harmony::SplitComplementary => (150.0, 210.0, 180.0),
+        }
+    }
+}
+
+/// Derives 7 color palettes from a base hue. The same settings always give the same palette.
+///
+/// - Dark, Bright and Skin are a shading ramp of the base hue:
+///   shadows lean toward blue and highlights toward yellow, like hand shaded pixel art
+/// - ShirtAccent1 is the base hue itself, the rest of the accents each get their own [`Harmony`] hue
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteGenerator {
+    /// degrees, 0 to 360
+    pub base_hue: f32,
+    /// 0 to 1, accents use it as is and the ramp is toned down from it
+    pub saturation: f32,
+    pub harmony: Harmony,
+}
+
+impl Default for PaletteGenerator {
+    fn default() -> Self {
+        Self::new(20.0)
+    }
+}
+
+impl PaletteGenerator {
+    pub fn new(base_hue: f32) -> Self {
+        Self {
+            base_hue: base_hue.rem_euclid(360.0),
+            saturation: 0.65,
+            harmony: Harmony::default(),
+        }
+    }
+
+    /// Settings taken from a color's hue and saturation. Greys give a red based palette with no saturation
+    pub fn from_color(color: &image::Rgba<u8>) -> Self {
+        let hsl = Hsl::from_rgba(color);
+        Self {
+            saturation: hsl.s,
+            ..Self::new(hsl.h)
+        }
+    }
+
+    /// Random looking settings picked from a seed
+    pub fn from_seed(seed: u64) -> Self {
+        let mut state = seed;
+        let base_hue = unit(splitmix64(&mut state)) * 360.0;
+        let saturation = 0.45 + unit(splitmix64(&mut state)) * 0.4;
+        let harmony = Harmony::ALL[(splitmix64(&mut state) % Harmony::ALL.len() as u64) as usize];
+        Self {
+            base_hue,
+            saturation,
+            harmony,
+        }
+    }
+
+    pub fn palette(&self) -> Palette<u8> {
+        let s = self.saturation;
+        let base = Hsl::new(self.base_hue, s, 0.5);
+        let (first, second, third) = self.harmony.offsets();
+        let accent = |offset: f32, s: f32, l: f32| Hsl::new(self.base_hue + offset, s, l).to_rgba(255);
+
+        Palette::new([
+            // Dark
+            Hsl { s: s * 0.5, l: 0.12, ..base }.shift_hue_toward(SHADOW_HUE, 25.0).to_rgba(255),
+            // Bright
+            Hsl { s: s * 0.4, l: 0.92, ..base }.shift_hue_toward(HIGHLIGHT_HUE, 25.0).to_rgba(255),
+            // Skin, the middle of the ramp
+            Hsl { s: s * 0.7, l: 0.62, ..base }.shift_hue_toward(HIGHLIGHT_HUE, 10.0).to_rgba(255),
+            // ShirtAccent1
+            accent(0.0, s, 0.45),
+            // PantsAccent2
+            accent(first, s * 0.8, 0.32),
+            // EyesAccent3
+            accent(second, f32::min(s + 0.2, 1.0), 0.55),
+            // Accent4
+            accent(third, s, 0.72),
+        ])
+    }
+
+    /// Fills all 8 palettes, each with the base hue turned another 45 degrees,
+    /// so every palette id in the DNA gives a different look
+    pub fn collection(&self) -> PaletteCollection<u8> {
+        PaletteCollection::new(std::array::from_fn(|i| Self {
+            base_hue: (self.base_hue + i as f32 * 45.0).rem_euclid(360.0),
+            ..*self
+        }.palette()))
+    }
+}
+
+impl PaletteCollection<u8> {
+    /// 8 unrelated generated palettes, each with its own settings picked from the seed
+    pub fn from_seed(seed: u64) -> Self {
+        let mut state = seed;
+        Self::new(std::array::from_fn(|_| PaletteGenerator::from_seed(splitmix64(&mut state)).palette()))
+    }
+}
+
+/// small deterministic generator, so seeds give the same palettes everywhere
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// top 24 bits as a float from 0 up to (not including) 1
+fn unit(value: u64) -> f32 {
+    (value >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ColorIndex;
+
+    #[test]
+    fn same_settings_same_palette() {
+        for seed in [0, 9, u64::MAX] {
+            assert_eq!(PaletteGenerator::from_seed(seed), PaletteGenerator::from_seed(seed));
+            assert_eq!(PaletteGenerator::from_seed(seed).palette(), PaletteGenerator::from_seed(seed).palette());
+            let (a, b) = (PaletteCollection::<u8>::from_seed(seed), PaletteCollection::<u8>::from_seed(seed));
+            assert!(a.iter().zip(b.iter()).all(|(a, b)| a == b));
+        }
+        assert_eq!(PaletteGenerator::new(200.0).palette(), PaletteGenerator::new(200.0 + 360.0).palette());
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let palettes: Vec<_> = (0..16).map(|seed| PaletteGenerator::from_seed(seed).palette()).collect();
+        for (i, a) in palettes.iter().enumerate() {
+            for b in &palettes[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_ne!(PaletteGenerator::new(20.0).palette(), PaletteGenerator::new(140.0).palette());
+    }
+
+    #[test]
+    fn accents_have_their_own_hues() {
+        let accents = [ColorIndex::ShirtAccent1, ColorIndex::PantsAccent2, ColorIndex::EyesAccent3, ColorIndex::Accent4];
+        for harmony in Harmony::ALL {
+            let palette = PaletteGenerator { harmony, ..PaletteGenerator::new(20.0) }.palette();
+            let hues: Vec<_> = accents.iter().map(|accent| Hsl::from_rgba(&palette[*accent]).h).collect();
+            for (i, a) in hues.iter().enumerate() {
+                for b in &hues[i + 1..] {
+                    // circular distance, rounding to 8 bit channels moves hues a little
+                    let distance = (a - b).rem_euclid(360.0);
+                    assert!(f32::min(distance, 360.0 - distance) > 20.0, "{harmony:?} {hues:?}");
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,31 @@
 use std::{ops::{Index, IndexMut}, fmt::Display};
 use bytemuck::{Zeroable, Pod, Contiguous};
 
+mod analyze;
+mod ascii;
+mod color;
+mod extract;
+mod generate;
+mod palette_file;
+mod pixel;
+mod roles;
+mod serialize;
+mod variants;
+
+pub use analyze::*;
+pub use ascii::*;
+pub use color::*;
+pub use extract::*;
+pub use generate::*;
+pub use palette_file::*;
+pub use pixel::*;
+pub use roles::*;
+pub use variants::*;
+
 
 /// A super small 3bit color index
-/// Represents a color type we can pick from our selected palette
+/// Represents a color type we can pick from our selected palette.
+/// The variant names are this crate's own roles, see [`ColorRoles`] for naming them per project
 #[repr(u8)]
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 // #[transparent(u8)]
@@ -43,15 +65,21 @@ impl Display for ColorIndex {
 }
 
 /// Representation of the Non-Encoded pixel bytes that are in the intermediary indexed format already.
-#[derive(Clone, Zeroable)]
+/// `Debug` and `FromStr` use an ascii grid, see [`ColorIndex::to_ascii`]
+#[derive(Clone, PartialEq)]
 // #[repr(C, packed)]
-pub struct IndexedImage<const N: usize, const W: usize> {
+/// The pixel type defaults to the 3 bit [`ColorIndex`], any [`IndexedPixel`] works
+/// (ex. `bool` for masks, [`TwoBitColorIndex`] for sprites, [`WideColorIndex`] for 15 color assets).
+pub struct IndexedImage<const N: usize, const W: usize, P = ColorIndex> {
     pub vertical_trim: u8,
     resolution: [u8;2],
-    pixels: [ColorIndex;N],
+    pixels: [P;N],
 }
 
-impl<const N: usize, const W: usize> IndexedImage<N,W> {
+// every field is plain bytes or zeroable pixels
+unsafe impl<const N: usize, const W: usize, P: Zeroable> Zeroable for IndexedImage<N,W,P> {}
+
+impl<const N: usize, const W: usize, P: IndexedPixel> IndexedImage<N,W,P> {
     /// shifts all pixels by (x, y) and drops any out of bounds
     pub fn shift(&mut self, x_offset: i32, y_offset: i32) {
         let res = self.resolution;
@@ -69,7 +97,7 @@ impl<const N: usize, const W: usize> IndexedImage<N,W> {
             || sample_y >= res[1] as i32
             || sample_y < 0
             {
-                ColorIndex::Empty
+                P::EMPTY
             } else {
                 sampler[(sample_x as usize, sample_y as usize)]
             };
@@ -77,17 +105,17 @@ impl<const N: usize, const W: usize> IndexedImage<N,W> {
     }
 }
 
-impl<const N: usize, const W: usize> Default for IndexedImage<N,W> {
+impl<const N: usize, const W: usize, P: IndexedPixel> Default for IndexedImage<N,W,P> {
     fn default() -> Self {
         Self {
             vertical_trim: 0,
             resolution: [W as u8,(N/W) as u8],
-            pixels: std::array::from_fn::<_,N,_>(|_| ColorIndex::Empty)
+            pixels: std::array::from_fn::<_,N,_>(|_| P::EMPTY)
         }
     }
 }
 
-impl<const N: usize,const W: usize> IndexedImage<N,W> {
+impl<const N: usize,const W: usize, P: IndexedPixel> IndexedImage<N,W,P> {
     pub fn new() -> Self {
         // panic if the inputted pixel count is not the same as array size
         // sadly static assertions are not working here
@@ -95,10 +123,13 @@ impl<const N: usize,const W: usize> IndexedImage<N,W> {
         IndexedImage {
             vertical_trim: 0,
             resolution: [W as u8,(N/W) as u8],
-            pixels: std::array::from_fn::<_,N,_>(|_| ColorIndex::Empty),
+            pixels: std::array::from_fn::<_,N,_>(|_| P::EMPTY),
         }
     }
-    pub fn enumerate_pixels(&self) -> EnumerateIndexedImage<N,W> {
+}
+
+impl<const N: usize,const W: usize, P> IndexedImage<N,W,P> {
+    pub fn enumerate_pixels(&self) -> EnumerateIndexedImage<'_,N,W,P> {
         EnumerateIndexedImage {
             image: self,
             x: 0,
@@ -106,7 +137,7 @@ impl<const N: usize,const W: usize> IndexedImage<N,W> {
         }
     }
 
-    pub fn enumerate_pixels_mut(&mut self) -> EnumerateIndexedImageMut<N,W> {
+    pub fn enumerate_pixels_mut(&mut self) -> EnumerateIndexedImageMut<'_,N,W,P> {
         EnumerateIndexedImageMut {
             image: self,
             x: 0,
@@ -115,8 +146,8 @@ impl<const N: usize,const W: usize> IndexedImage<N,W> {
     }
 }
 
-impl<const N: usize, const W: usize> Index<(usize, usize)> for IndexedImage<N,W> {
-    type Output = ColorIndex;
+impl<const N: usize, const W: usize, P> Index<(usize, usize)> for IndexedImage<N,W,P> {
+    type Output = P;
 
     fn index(&self, index: (usize, usize)) -> &Self::Output {
         // total pixel count (N) must always be divisible by WIDTH
@@ -125,23 +156,23 @@ impl<const N: usize, const W: usize> Index<(usize, usize)> for IndexedImage<N,W>
         // calculate flat index into the array
         let index = 
             self.resolution[0] as usize // width
-            * index.1 as usize // y
-            + index.0 as usize; // x
+            * index.1 // y
+            + index.0; // x
 
         &self.pixels[index]
     }
 }
 
-impl<const N: usize, const W: usize> IndexMut<(usize, usize)> for IndexedImage<N,W> {
-    fn index_mut(&mut self, index: (usize, usize)) -> &mut ColorIndex {
+impl<const N: usize, const W: usize, P> IndexMut<(usize, usize)> for IndexedImage<N,W,P> {
+    fn index_mut(&mut self, index: (usize, usize)) -> &mut P {
         // total pixel count (N) must always be divisible by WIDTH
         debug_assert_eq!(N % W, 0);
 
         // calculate flat index into the array
         let index = 
             self.resolution[0] as usize // width
-            * index.1 as usize // y
-            + index.0 as usize; // x
+            * index.1 // y
+            + index.0; // x
 
         &mut self.pixels[index]
     }
@@ -149,14 +180,14 @@ impl<const N: usize, const W: usize> IndexMut<(usize, usize)> for IndexedImage<N
 
 /// for enumerating pixel contents with ease.
 /// Based on the enumerate pixels system of the image crate.
-pub struct EnumerateIndexedImage<'a, const N: usize, const W: usize> {
-    image: &'a IndexedImage<N,W>,
+pub struct EnumerateIndexedImage<'a, const N: usize, const W: usize, P = ColorIndex> {
+    image: &'a IndexedImage<N,W,P>,
     x: u8,
     y: u8,
 }
 
-impl<'a, const N: usize, const W: usize> Iterator for EnumerateIndexedImage<'a,N,W> {
-    type Item = (u8,u8, &'a ColorIndex);
+impl<'a, const N: usize, const W: usize, P> Iterator for EnumerateIndexedImage<'a,N,W,P> {
+    type Item = (u8,u8, &'a P);
 
     #[inline(always)]
     fn next(&mut self) -> Option<Self::Item> {
@@ -189,15 +220,15 @@ impl<'a, const N: usize, const W: usize> Iterator for EnumerateIndexedImage<'a,N
     }
 }
 
-pub struct EnumerateIndexedImageMut<'a, const N: usize, const W: usize>
+pub struct EnumerateIndexedImageMut<'a, const N: usize, const W: usize, P = ColorIndex>
 {
-    image: &'a mut IndexedImage<N,W>,
+    image: &'a mut IndexedImage<N,W,P>,
     x: u8,
     y: u8,
 }
 
-impl<'a, const N: usize, const W: usize> Iterator for EnumerateIndexedImageMut<'a,N,W> {
-    type Item = (u8,u8, &'a mut ColorIndex) where Self::Item: 'a;
+impl<'a, const N: usize, const W: usize, P> Iterator for EnumerateIndexedImageMut<'a,N,W,P> {
+    type Item = (u8,u8, &'a mut P);
 
     #[inline(always)]
     fn next(& mut self) -> Option<Self::Item>
@@ -251,53 +282,149 @@ impl<'a, const N: usize, const W: usize> Iterator for EnumerateIndexedImageMut<'
 }
 
 // collection of pallets (max 8)
-pub struct PaletteCollection<T> {
-    palettes: [Palette<T>;8]
+pub struct PaletteCollection<T, const C: usize = 7> {
+    palettes: [Palette<T, C>;8]
+}
+
+impl<T, const C: usize> PaletteCollection<T, C> {
+    pub fn new(palettes: [Palette<T, C>;8]) -> Self {
+        Self { palettes }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Palette<T, C>> {
+        self.palettes.iter()
+    }
 }
 
-impl Default for PaletteCollection<u8> {
+impl<const C: usize> Default for PaletteCollection<u8, C> where Palette<u8, C>: Default {
     fn default() -> Self {
         Self { palettes: Default::default() }
     }
 }
 
-impl<T> Index<u8> for PaletteCollection<T> {
-    type Output = Palette<T>;
+impl<T, const C: usize> Index<u8> for PaletteCollection<T, C> {
+    type Output = Palette<T, C>;
 
     fn index(&self, index: u8) -> &Self::Output {
         self.palettes.index(index as usize)
     }
 }
 
-impl<T> IndexMut<u8> for PaletteCollection<T> {
+impl<T, const C: usize> IndexMut<u8> for PaletteCollection<T, C> {
     fn index_mut(&mut self, index: u8) -> &mut Self::Output {
         self.palettes.index_mut(index as usize)
     }
 }
 
-pub struct Palette<T> {
-    contents: [image::Rgb<T>;7],
+/// Colors for every non Empty color index. Holds 7 colors for [`ColorIndex`] by default,
+/// size it with [`IndexedPixel::COLORS`] for other pixel types (ex. 15 for [`WideColorIndex`]).
+/// Colors have straight (not premultiplied) alpha, so entries can be see through for shadows or glass,
+/// Empty is still the only fully transparent index
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette<T, const C: usize = 7> {
+    contents: [image::Rgba<T>;C],
+}
+
+impl<T, const C: usize> Palette<T, C> {
+    /// first color is for index 1, since Empty has no color
+    pub fn new(contents: [image::Rgba<T>;C]) -> Self {
+        Self { contents }
+    }
+
+    /// Returns None for Empty and for indices past the end of the palette
+    pub fn get<P: IndexedPixel>(&self, index: P) -> Option<&image::Rgba<T>> {
+        self.contents.get((index.to_u8() as usize).checked_sub(1)?)
+    }
+
+    /// every color, starting at index 1
+    pub fn colors(&self) -> &[image::Rgba<T>;C] {
+        &self.contents
+    }
+}
+
+impl<T: image::Primitive, const C: usize> Palette<T, C> {
+    /// Palette of fully opaque colors
+    pub fn from_rgb(contents: [image::Rgb<T>;C]) -> Self {
+        Self { contents: contents.map(|image::Rgb([r, g, b])| image::Rgba([r, g, b, T::DEFAULT_MAX_VALUE])) }
+    }
+
+    /// true if no color has any transparency
+    pub fn is_opaque(&self) -> bool {
+        self.contents.iter().all(|color| color.0[3] == T::DEFAULT_MAX_VALUE)
+    }
+}
+
+impl<const C: usize> Palette<u8, C> {
+    /// Packs the palette as RGB8 for every color, followed by the alpha of every color.
+    /// The alpha bytes are left out when the whole palette is opaque, so old 3 byte per color packs still load
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = self.contents.iter().flat_map(|color| [color.0[0], color.0[1], color.0[2]]).collect();
+        if !self.is_opaque() {
+            out.extend(self.contents.iter().map(|color| color.0[3]));
+        }
+        out
+    }
+
+    /// Reads a pack written by [`Self::to_bytes`], None unless it's exactly 3 or 4 bytes per color
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (rgb, alpha) = match bytes.len() {
+            len if len == C * 3 => (bytes, None),
+            len if len == C * 4 => {
+                let (rgb, alpha) = bytes.split_at(C * 3);
+                (rgb, Some(alpha))
+            },
+            _ => return None,
+        };
+        Some(Self { contents: std::array::from_fn(|i| image::Rgba([
+            rgb[i * 3],
+            rgb[i * 3 + 1],
+            rgb[i * 3 + 2],
+            alpha.map_or(255, |alpha| alpha[i]),
+        ])) })
+    }
 }
 
-impl<T> Index<ColorIndex> for Palette<T> {
-    type Output = image::Rgb<T>;
+impl<T, const C: usize, P: IndexedPixel> Index<P> for Palette<T, C> {
+    type Output = image::Rgba<T>;
 
-    /// fails if you try to index EmptyBrush
-    fn index(&self, index: ColorIndex) -> &Self::Output {
-        &self.contents[index as usize -1]
+    /// fails if you try to index Empty, or past the end of the palette
+    fn index(&self, index: P) -> &Self::Output {
+        &self.contents[index.to_u8() as usize -1]
     }
 }
 
+impl<T, const C: usize, P: IndexedPixel> IndexMut<P> for Palette<T, C> {
+    fn index_mut(&mut self, index: P) -> &mut Self::Output {
+        &mut self.contents[index.to_u8() as usize -1]
+    }
+}
+
+const DEFAULT_COLORS: [image::Rgba<u8>;7] = [
+    image::Rgba([0,0,0,255]),
+    image::Rgba([255,255,255,255]),
+    image::Rgba([204,164,153,255]),
+    image::Rgba([255,165,96,255]),
+    image::Rgba([101,107,255,255]),
+    image::Rgba([173,101,255,255]),
+    image::Rgba([62,24,24,255]),
+];
+
 impl Default for Palette<u8> {
     fn default() -> Self {
-        Self { contents: [
-            image::Rgb([0,0,0]),
-            image::Rgb([255,255,255]),
-            image::Rgb([204,164,153]),
-            image::Rgb([255,165,96]),
-            image::Rgb([101,107,255]),
-            image::Rgb([173,101,255]),
-            image::Rgb([62,24,24]),
-        ] }
+        Self { contents: DEFAULT_COLORS }
     }
-}
\ No newline at end of file
+}
+
+/// the default colors, then a darker shade of each, then a mid grey
+impl Default for Palette<u8, 15> {
+    fn default() -> Self {
+        Self { contents: std::array::from_fn(|i| match i {
+            0..=6 => DEFAULT_COLORS[i],
+            7..=13 => {
+                let [r, g, b, a] = DEFAULT_COLORS[i - 7].0;
+                image::Rgba([r / 2, g / 2, b / 2, a])
+            },
+            _ => image::Rgba([128,128,128,255]),
+        }) }
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt::Write;
+use bevy::log::{info, warn};
+use super::{serialize::color_from_hex, ColorRoles, Palette, PaletteCollection};
+
+/// Palette files artists get their colors from
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PaletteFileFormat {
+    /// GIMP `.gpl`, `R G B name` per line after a small header
+    Gpl,
+    /// JASC (Paint Shop Pro) `.pal`, a color count then `R G B` per line
+    JascPal,
+    /// Lospec `.hex`, `rrggbb` per line
+    LospecHex,
+    /// Paint.NET `.txt`, `AARRGGBB` per line, `;` comments
+    PaintNet,
+}
+
+impl PaletteFileFormat {
+    pub const ALL: [PaletteFileFormat; 4] = [
+        PaletteFileFormat::Gpl,
+        PaletteFileFormat::JascPal,
+        PaletteFileFormat::LospecHex,
+        PaletteFileFormat::PaintNet,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFileFormat::Gpl => "gpl",
+            PaletteFileFormat::JascPal => "pal",
+            PaletteFileFormat::LospecHex => "hex",
+            PaletteFileFormat::PaintNet => "txt",
+        }
+    }
+
+    /// case insensitive, without the dot
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+/// Reads every color in a palette file, in file order. Formats without alpha give opaque colors.
+/// Returns None (and warns) if the header or any color line is invalid
+pub fn parse_palette_file(text: &str, format: PaletteFileFormat) -> Option<Vec<image::Rgba<u8>>> {
+    let mut lines = text.lines().map(str::trim);
+    let colors = match format {
+        PaletteFileFormat::Gpl => {
+            if lines.next() != Some("GIMP Palette") {
+                warn!("MISSING GIMP PALETTE HEADER");
+                return None;
+            }
+            lines
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter(|line| !line.starts_with("Name:") && !line.starts_with("Columns:"))
+                .map(|line| rgb_from_decimal(line, 3))
+                .collect::<Option<Vec<_>>>()
+        },
+        PaletteFileFormat::JascPal => {
+            if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+                warn!("MISSING JASC-PAL HEADER");
+                return None;
+            }
+            let count: usize = lines.next()?.parse().ok()?;
+            // some tools add a 4th alpha column
+            let colors = lines
+                .filter(|line| !line.is_empty())
+                .map(|line| rgb_from_decimal(line, 4))
+                .collect::<Option<Vec<_>>>()?;
+            if colors.len() != count {
+                warn!("jasc palette says {count} colors but has {}", colors.len());
+            }
+            Some(colors)
+        },
+        PaletteFileFormat::LospecHex => lines
+            .filter(|line| !line.is_empty())
+            .map(color_from_hex)
+            .collect(),
+        PaletteFileFormat::PaintNet => lines
+            .filter(|line| !line.is_empty() && !line.starts_with(';'))
+            .map(|line| match line.len() {
+                // alpha comes first
+                8 if line.is_ascii() => color_from_hex(&format!("{}{}", &line[2..], &line[..2])),
+                _ => color_from_hex(line),
+            })
+            .collect(),
+    };
+    if colors.is_none() {
+        warn!("INVALID COLOR IN {} PALETTE", format.extension());
+    }
+    colors
+}
+
+/// `R G B` with optional extra columns: up to `alpha_column` numbers are read as RGB(A),
+/// anything after them (ex. a gpl color name) is ignored
+fn rgb_from_decimal(line: &str, alpha_column: usize) -> Option<image::Rgba<u8>> {
+    let mut channels = [255; 4];
+    let mut words = line.split_whitespace();
+    for (i, channel) in channels.iter_mut().enumerate().take(alpha_column) {
+        match (i, words.next().map(str::parse::<u8>)) {
+            (_, Some(Ok(value))) => *channel = value,
+            // alpha is optional
+            (3, None) => break,
+            _ => return None,
+        }
+    }
+    Some(image::Rgba(channels))
+}
+
+/// Writes colors in a palette file. `names` label the colors in formats that support it (gpl).
+/// Formats without alpha drop it, with a warning if any color wasn't opaque
+pub fn write_palette_file(colors: &[image::Rgba<u8>], names: &[String], format: PaletteFileFormat, name: &str) -> String {
+    if format != PaletteFileFormat::PaintNet && colors.iter().any(|color| color.0[3] != 255) {
+        warn!("{} palettes have no alpha, writing translucent colors as opaque", format.extension());
+    }
+    let mut out = String::new();
+    // writing to a String can't fail
+    match format {
+        PaletteFileFormat::Gpl => {
+            let _ = write!(out, "GIMP Palette\nName: {name}\nColumns: 0\n#\n");
+            for (i, image::Rgba([r, g, b, _])) in colors.iter().enumerate() {
+                let color_name = names.get(i).map_or("Untitled", String::as_str);
+                let _ = writeln!(out, "{r:>3} {g:>3} {b:>3}\t{color_name}");
+            }
+        },
+        PaletteFileFormat::JascPal => {
+            let _ = write!(out, "JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+            for image::Rgba([r, g, b, _]) in colors {
+                let _ = write!(out, "{r} {g} {b}\r\n");
+            }
+        },
+        PaletteFileFormat::LospecHex => {
+            for image::Rgba([r, g, b, _]) in colors {
+                let _ = writeln!(out, "{r:02x}{g:02x}{b:02x}");
+            }
+        },
+        PaletteFileFormat::PaintNet => {
+            let _ = write!(out, "; paint.net Palette File\n; Palette Name: {name}\n; Colors: {}\n", colors.len());
+            for image::Rgba([r, g, b, a]) in colors {
+                let _ = writeln!(out, "{a:02X}{r:02X}{g:02X}{b:02X}");
+            }
+        },
+    }
+    out
+}
+
+/// the default role names, numbered past them
+fn color_names(count: usize, roles: &ColorRoles) -> Vec<String> {
+    (1..=count)
+        .map(|i| roles.iter().nth(i).map_or_else(|| format!("Color {i}"), |(_, role)| role.name.clone()))
+        .collect()
+}
+
+impl<const C: usize> Palette<u8, C> where Self: Default {
+    /// The first color goes to index 1 (Dark), the next to index 2 (Bright) and so on.
+    /// Colors past the palette size are dropped with a warning,
+    /// and indices without a color keep their default one
+    pub fn from_colors(colors: &[image::Rgba<u8>]) -> Self {
+        let mut palette = Self::default();
+        if colors.len() > C {
+            warn!("palette has {} colors, only the first {C} are used", colors.len());
+        } else if colors.len() < C {
+            warn!("palette only has {} colors, the rest keep their defaults", colors.len());
+        }
+        let roles = color_names(C, &ColorRoles::default());
+        for (i, color) in colors.iter().take(C).enumerate() {
+            info!("{} = {:?}", roles[i], color.0);
+        }
+        for (entry, color) in palette.contents.iter_mut().zip(colors) {
+            *entry = *color;
+        }
+        palette
+    }
+
+    pub fn from_palette_file(text: &str, format: PaletteFileFormat) -> Option<Self> {
+        Some(Self::from_colors(&parse_palette_file(text, format)?))
+    }
+}
+
+impl<const C: usize> Palette<u8, C> {
+    /// gpl colors are named after the default [`ColorRoles`]
+    pub fn to_palette_file(&self, format: PaletteFileFormat, name: &str) -> String {
+        write_palette_file(&self.contents, &color_names(C, &ColorRoles::default()), format, name)
+    }
+}
+
+impl<const C: usize> PaletteCollection<u8, C> where Palette<u8, C>: Default {
+    /// Splits the colors into palettes of `C` colors, in palette order.
+    /// Colors past the 8th palette are dropped with a warning, and palettes without colors keep their defaults
+    pub fn from_colors(colors: &[image::Rgba<u8>]) -> Self {
+        if colors.len() > C * 8 {
+            warn!("palette has {} colors, only the first {} are used", colors.len(), C * 8);
+        }
+        let mut collection = Self::default();
+        for (i, chunk) in colors.chunks(C).take(8).enumerate() {
+            collection[i as u8] = Palette::from_colors(chunk);
+        }
+        collection
+    }
+
+    pub fn from_palette_file(text: &str, format: PaletteFileFormat) -> Option<Self> {
+        Some(Self::from_colors(&parse_palette_file(text, format)?))
+    }
+}
+
+impl<const C: usize> PaletteCollection<u8, C> {
+    /// Writes all 8 palettes one after another, gpl colors are named `palette:role`
+    pub fn to_palette_file(&self, format: PaletteFileFormat, name: &str) -> String {
+        let roles = color_names(C, &ColorRoles::default());
+        let colors: Vec<_> = self.iter().flat_map(|palette| palette.contents).collect();
+        let names: Vec<_> = (0..8).flat_map(|p| roles.iter().map(move |role| format!("{p}:{role}"))).collect();
+        write_palette_file(&colors, &names, format, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ColorIndex;
+
+    fn translucent() -> Palette<u8> {
+        let mut palette = Palette::default();
+        palette[ColorIndex::Skin] = image::Rgba([10, 20, 30, 128]);
+        palette
+    }
+
+    #[test]
+    fn every_format_round_trips_opaque_colors() {
+        for format in PaletteFileFormat::ALL {
+            let text = Palette::<u8>::default().to_palette_file(format, "test");
+            assert_eq!(Palette::<u8>::from_palette_file(&text, format), Some(Palette::default()), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn alpha_only_survives_paint_net() {
+        let text = translucent().to_palette_file(PaletteFileFormat::PaintNet, "test");
+        assert_eq!(Palette::<u8>::from_palette_file(&text, PaletteFileFormat::PaintNet), Some(translucent()));
+        let text = translucent().to_palette_file(PaletteFileFormat::Gpl, "test");
+        let back = Palette::<u8>::from_palette_file(&text, PaletteFileFormat::Gpl).unwrap();
+        assert_eq!(back[ColorIndex::Skin], image::Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn reads_jasc_alpha_column() {
+        let text = "JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n4 5 6 128\r\n";
+        let palette = Palette::<u8>::from_palette_file(text, PaletteFileFormat::JascPal).unwrap();
+        assert_eq!(palette[ColorIndex::Dark], image::Rgba([1, 2, 3, 255]));
+        assert_eq!(palette[ColorIndex::Bright], image::Rgba([4, 5, 6, 128]));
+        // indices without a color keep their default
+        assert_eq!(palette[ColorIndex::Skin], Palette::<u8>::default()[ColorIndex::Skin]);
+    }
+
+    #[test]
+    fn gpl_names_come_from_the_roles() {
+        let text = PaletteCollection::<u8>::default().to_palette_file(PaletteFileFormat::Gpl, "all");
+        assert!(text.contains("Name: all"));
+        assert!(text.contains("\t0:Dark"));
+        assert!(text.contains("\t7:Accent4"));
+        let back = PaletteCollection::<u8>::from_palette_file(&text, PaletteFileFormat::Gpl).unwrap();
+        assert!(back.iter().zip(PaletteCollection::<u8>::default().iter()).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert_eq!(parse_palette_file("1 2 3", PaletteFileFormat::Gpl), None);
+        assert_eq!(parse_palette_file("GIMP Palette\n1 2 300", PaletteFileFormat::Gpl), None);
+        assert_eq!(parse_palette_file("0100\n1\n1 2 3", PaletteFileFormat::JascPal), None);
+        assert_eq!(parse_palette_file("ggggggg", PaletteFileFormat::LospecHex), None);
+    }
+
+    #[test]
+    fn extensions_are_case_insensitive() {
+        assert_eq!(PaletteFileFormat::from_extension("GPL"), Some(PaletteFileFormat::Gpl));
+        assert_eq!(PaletteFileFormat::from_extension("png"), None);
+        for format in PaletteFileFormat::ALL {
+            assert_eq!(PaletteFileFormat::from_extension(format.extension()), Some(format));
+        }
+    }
+}
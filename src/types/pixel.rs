@@ -0,0 +1,121 @@
+use std::fmt::Display;
+use bytemuck::{Zeroable, Contiguous};
+use super::ColorIndex;
+
+/// Any indexed pixel type an [`super::IndexedImage`] can hold.
+/// Index 0 is always Empty, and the rest pick a color from a palette
+pub trait IndexedPixel: Copy + PartialEq + Zeroable {
+    /// bits one index takes when encoded
+    const BITS: u8;
+    const EMPTY: Self;
+    /// how many non Empty colors there are, the size of a palette for this type
+    const COLORS: usize = (0x1 << Self::BITS) - 1;
+
+    fn to_u8(self) -> u8;
+
+    /// Returns None for values that don't fit in `BITS`
+    fn from_u8(value: u8) -> Option<Self>;
+}
+
+/// the current 3 bit assets
+impl IndexedPixel for ColorIndex {
+    const BITS: u8 = 3;
+    const EMPTY: Self = ColorIndex::Empty;
+
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        ColorIndex::from_integer(value)
+    }
+}
+
+/// 1 bit masks, set pixels are the single color
+impl IndexedPixel for bool {
+    const BITS: u8 = 1;
+    const EMPTY: Self = false;
+
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// A color index of `BITS` bits, for depths without named roles.
+/// 0 means Empty, leaving `2^BITS - 1` color options
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PackedIndex<const BITS: u8>(u8);
+
+/// 2 bit sprites, 3 colors
+pub type TwoBitColorIndex = PackedIndex<2>;
+/// A 4bit color index for shading heavy assets (ex. backgrounds) that need more than 7 colors.
+/// 15 colors
+pub type WideColorIndex = PackedIndex<4>;
+
+// a single u8 and 0 is Empty. Not Pod, values past `MAX` would be invalid indices
+unsafe impl<const BITS: u8> Zeroable for PackedIndex<BITS> {}
+
+impl<const BITS: u8> PackedIndex<BITS> {
+    pub const EMPTY: Self = Self(0);
+    /// highest color index
+    pub const MAX: u8 = (0x1 << BITS) - 1;
+
+    /// Returns None for indices that don't fit in `BITS`
+    pub fn new(index: u8) -> Option<Self> {
+        (index <= Self::MAX).then_some(Self(index))
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl<const BITS: u8> IndexedPixel for PackedIndex<BITS> {
+    const BITS: u8 = BITS;
+    const EMPTY: Self = Self(0);
+
+    fn to_u8(self) -> u8 {
+        self.0
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        Self::new(value)
+    }
+}
+
+/// every 3 bit index keeps its value
+impl From<ColorIndex> for WideColorIndex {
+    fn from(color: ColorIndex) -> Self {
+        Self(color as u8)
+    }
+}
+
+impl<const BITS: u8> Display for PackedIndex<BITS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_indices_stay_within_bits() {
+        assert_eq!(TwoBitColorIndex::MAX, 3);
+        assert_eq!(WideColorIndex::MAX, 15);
+        assert_eq!(WideColorIndex::new(15).map(|i| i.get()), Some(15));
+        assert_eq!(WideColorIndex::new(16), None);
+        assert_eq!(TwoBitColorIndex::from_u8(4), None);
+        assert_eq!(WideColorIndex::zeroed(), WideColorIndex::EMPTY);
+    }
+}
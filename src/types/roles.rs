@@ -0,0 +1,168 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use super::{ColorIndex, IndexedPixel};
+
+/// What a color index is used for, shown by tools instead of the bare number
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRole {
+    pub name: String,
+    /// short text or emoji the editor draws on the color's button
+    pub icon: String,
+}
+
+impl ColorRole {
+    pub fn new(name: impl Into<String>, icon: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            icon: icon.into(),
+        }
+    }
+}
+
+/// Roles for every color index of a palette or project, Empty (index 0) first.
+/// Roles are only for tools and people, the numeric index is all that ever gets encoded,
+/// so two projects can give the same index different roles and still share assets.
+///
+/// Serialized as a list of `[name, icon]` pairs, so they can live in a project's config,
+/// or written as a roles file (see [`ColorRoles::to_roles_file`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRoles {
+    roles: Vec<ColorRole>,
+}
+
+impl ColorRoles {
+    /// first role is for Empty
+    pub fn new(roles: Vec<ColorRole>) -> Self {
+        Self { roles }
+    }
+
+    /// Numbered roles without icons for a pixel type, for projects that don't name their colors
+    pub fn numbered<P: IndexedPixel>() -> Self {
+        let roles = (0..=P::COLORS)
+            .map(|i| match i {
+                0 => ColorRole::new("Empty", "❌"),
+                _ => ColorRole::new(format!("Color {i}"), i.to_string()),
+            })
+            .collect();
+        Self { roles }
+    }
+
+    /// Returns None for indices without a role
+    pub fn get<P: IndexedPixel>(&self, index: P) -> Option<&ColorRole> {
+        self.roles.get(index.to_u8() as usize)
+    }
+
+    /// the role's name, or the index itself when it has none
+    pub fn name<P: IndexedPixel>(&self, index: P) -> String {
+        self.get(index).map_or_else(|| index.to_u8().to_string(), |role| role.name.clone())
+    }
+
+    /// Finds the index of the role with the given name
+    pub fn find<P: IndexedPixel>(&self, name: &str) -> Option<P> {
+        let index = self.roles.iter().position(|role| role.name == name)?;
+        P::from_u8(index as u8)
+    }
+
+    /// Iterates every role along with its index, Empty included
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &ColorRole)> {
+        self.roles.iter().enumerate().map(|(i, role)| (i as u8, role))
+    }
+
+    /// Plain text with one role per line in index order, the name and icon separated by a tab
+    pub fn to_roles_file(&self) -> String {
+        self.roles.iter().map(|role| format!("{}\t{}\n", role.name, role.icon)).collect()
+    }
+
+    /// Reads [`ColorRoles::to_roles_file`] text, blank lines and lines starting with `#` are skipped
+    /// and a line without a tab is a role without an icon.
+    /// Returns None if there are no roles
+    pub fn from_roles_file(text: &str) -> Option<Self> {
+        let roles: Vec<_> = text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (name, icon) = line.split_once('\t').unwrap_or((line, ""));
+                ColorRole::new(name.trim(), icon.trim())
+            })
+            .collect();
+        if roles.is_empty() {
+            return None;
+        }
+        Some(Self { roles })
+    }
+}
+
+/// icons of the default roles, in color index order
+const DEFAULT_ICONS: [&str; 8] = ["❌", "🎩", "🌞", "👨", "👕", "👖", "👀", "🎨"];
+
+/// The roles the [`ColorIndex`] variants are named after, names come from [`ColorIndex::role_name`]
+impl Default for ColorRoles {
+    fn default() -> Self {
+        let roles = DEFAULT_ICONS.iter()
+            .enumerate()
+            .filter_map(|(i, icon)| Some(ColorRole::new(ColorIndex::from_u8(i as u8)?.role_name(), *icon)))
+            .collect();
+        Self { roles }
+    }
+}
+
+impl Serialize for ColorRoles {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.roles.iter().map(|role| (&role.name, &role.icon)))
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorRoles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = Vec::<(String, String)>::deserialize(deserializer)?;
+        Ok(Self::new(pairs.into_iter().map(|(name, icon)| ColorRole { name, icon }).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::WideColorIndex;
+
+    #[test]
+    fn default_names_match_the_role_names() {
+        let roles = ColorRoles::default();
+        assert_eq!(roles.iter().count(), ColorIndex::COLORS + 1);
+        for (index, role) in roles.iter() {
+            let color = ColorIndex::from_u8(index).unwrap();
+            assert_eq!(role.name, color.role_name());
+            assert_eq!(roles.find::<ColorIndex>(&role.name), Some(color));
+        }
+    }
+
+    #[test]
+    fn numbered_covers_every_index() {
+        let roles = ColorRoles::numbered::<WideColorIndex>();
+        assert_eq!(roles.iter().count(), 16);
+        assert_eq!(roles.name(WideColorIndex::new(15).unwrap()), "Color 15");
+        assert_eq!(ColorRoles::numbered::<bool>().name(true), "Color 1");
+    }
+
+    #[test]
+    fn missing_roles_fall_back_to_the_index() {
+        let roles = ColorRoles::new(vec![ColorRole::new("Empty", "")]);
+        assert_eq!(roles.get(ColorIndex::Skin), None);
+        assert_eq!(roles.name(ColorIndex::Skin), "3");
+    }
+
+    #[test]
+    fn roles_file_round_trip() {
+        for roles in [ColorRoles::default(), ColorRoles::numbered::<WideColorIndex>()] {
+            assert_eq!(ColorRoles::from_roles_file(&roles.to_roles_file()), Some(roles));
+        }
+        let roles = ColorRoles::from_roles_file("# my project\n\nEmpty\nOutline\t🖊\n").unwrap();
+        assert_eq!(roles.get(ColorIndex::Empty), Some(&ColorRole::new("Empty", "")));
+        assert_eq!(roles.name(ColorIndex::Dark), "Outline");
+        assert_eq!(ColorRoles::from_roles_file("# nothing\n"), None);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = serde_json::to_string(&ColorRoles::default()).unwrap();
+        assert!(json.starts_with(r#"[["Empty","❌"],["Dark","🎩"]"#));
+        assert_eq!(serde_json::from_str::<ColorRoles>(&json).unwrap(), ColorRoles::default());
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+use bytemuck::Contiguous;
+use serde::{de::{self, Visitor, EnumAccess, VariantAccess, SeqAccess}, Deserialize, Deserializer, Serialize, Serializer};
+use super::{ColorIndex, IndexedImage, Palette, PaletteCollection};
+
+/// role names in color index order, used as the serialized form of [`ColorIndex`]
+const ROLE_NAMES: &[&str] = &["Empty", "Dark", "Bright", "Skin", "ShirtAccent1", "PantsAccent2", "EyesAccent3", "Accent4"];
+
+impl ColorIndex {
+    /// name of the role this index plays, same as the variant name
+    pub fn role_name(&self) -> &'static str {
+        ROLE_NAMES[*self as usize]
+    }
+
+    pub fn from_role_name(name: &str) -> Option<Self> {
+        let index = ROLE_NAMES.iter().position(|n| *n == name)?;
+        ColorIndex::from_integer(index as u8)
+    }
+}
+
+/// Serialized as a unit variant, so self describing formats get the role name
+/// and binary formats get the variant index
+impl Serialize for ColorIndex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit_variant("ColorIndex", *self as u32, self.role_name())
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorIndex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum("ColorIndex", ROLE_NAMES, ColorIndexVisitor)
+    }
+}
+
+struct ColorIndexVisitor;
+
+impl<'de> Visitor<'de> for ColorIndexVisitor {
+    type Value = ColorIndex;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a color role name or an index from 0 to 7")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        ColorIndex::from_role_name(v).ok_or_else(|| E::unknown_variant(v, ROLE_NAMES))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        u8::try_from(v).ok()
+            .and_then(ColorIndex::from_integer)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (RoleName(color), variant) = data.variant()?;
+        variant.unit_variant()?;
+        Ok(color)
+    }
+}
+
+/// variant identifier of a serialized [`ColorIndex`], either the name or the index
+struct RoleName(ColorIndex);
+
+impl<'de> Deserialize<'de> for RoleName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_identifier(ColorIndexVisitor).map(RoleName)
+    }
+}
+
+/// Serialized as one string per row, each pixel written as its color index digit (`0` for Empty).
+/// The vertical trim is an encoder setting, so it isn't included
+impl<const N: usize, const W: usize> Serialize for IndexedImage<N, W> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut rows = vec![String::with_capacity(W); N / W];
+        for (_, y, p) in self.enumerate_pixels() {
+            rows[y as usize].push(char::from(b'0' + *p as u8));
+        }
+        serializer.collect_seq(rows)
+    }
+}
+
+impl<'de, const N: usize, const W: usize> Deserialize<'de> for IndexedImage<N, W> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(IndexedImageVisitor::<N, W>)
+    }
+}
+
+struct IndexedImageVisitor<const N: usize, const W: usize>;
+
+impl<'de, const N: usize, const W: usize> Visitor<'de> for IndexedImageVisitor<N, W> {
+    type Value = IndexedImage<N, W>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rows of {} color index digits", N / W, W)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut image = IndexedImage::new();
+        let mut y = 0;
+        while let Some(row) = seq.next_element::<String>()? {
+            if y >= N / W || row.chars().count() != W {
+                return Err(de::Error::invalid_length(y + 1, &self));
+            }
+            for (x, c) in row.chars().enumerate() {
+                image[(x, y)] = c.to_digit(10)
+                    .and_then(|d| ColorIndex::from_integer(d as u8))
+                    .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Char(c), &"a color index digit from 0 to 7"))?;
+            }
+            y += 1;
+        }
+        if y != N / W {
+            return Err(de::Error::invalid_length(y, &self));
+        }
+        Ok(image)
+    }
+}
+
+/// `#rrggbb`, or `#rrggbbaa` for colors that aren't fully opaque
+pub(super) fn color_to_hex(color: &image::Rgba<u8>) -> String {
+    match color.0 {
+        [r, g, b, 255] => format!("#{r:02x}{g:02x}{b:02x}"),
+        [r, g, b, a] => format!("#{r:02x}{g:02x}{b:02x}{a:02x}"),
+    }
+}
+
+/// Reads `rrggbb` or `rrggbbaa`, with or without the leading `#`. Colors without alpha are opaque
+pub(super) fn color_from_hex(hex: &str) -> Option<image::Rgba<u8>> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| hex.get(i * 2..i * 2 + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    Some(image::Rgba([
+        channel(0)?,
+        channel(1)?,
+        channel(2)?,
+        if hex.len() == 8 { channel(3)? } else { 255 },
+    ]))
+}
+
+/// Serialized as a list of hex colors (see [`color_to_hex`]) for self describing formats,
+/// and as the [`Palette::to_bytes`] pack for binary ones
+impl<const C: usize> Serialize for Palette<u8, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_seq(self.colors().iter().map(color_to_hex))
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de, const C: usize> Deserialize<'de> for Palette<u8, C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_seq(PaletteVisitor::<C>)
+        } else {
+            deserializer.deserialize_bytes(PaletteVisitor::<C>)
+        }
+    }
+}
+
+struct PaletteVisitor<const C: usize>;
+
+impl<'de, const C: usize> Visitor<'de> for PaletteVisitor<C> {
+    type Value = Palette<u8, C>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{C} hex colors, or a palette pack of {} or {} bytes", C * 3, C * 4)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut colors = Vec::with_capacity(C);
+        while let Some(hex) = seq.next_element::<String>()? {
+            let color = color_from_hex(&hex)
+                .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&hex), &"a #rrggbb or #rrggbbaa color"))?;
+            colors.push(color);
+        }
+        let length = colors.len();
+        let contents = colors.try_into().map_err(|_| de::Error::invalid_length(length, &self))?;
+        Ok(Palette::new(contents))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Palette::from_bytes(v).ok_or_else(|| E::invalid_length(v.len(), &self))
+    }
+}
+
+/// Serialized as a list of all 8 palettes
+impl<const C: usize> Serialize for PaletteCollection<u8, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, const C: usize> Deserialize<'de> for PaletteCollection<u8, C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let palettes = Vec::<Palette<u8, C>>::deserialize(deserializer)?;
+        let length = palettes.len();
+        let palettes = palettes.try_into()
+            .map_err(|_| de::Error::invalid_length(length, &"8 palettes"))?;
+        Ok(PaletteCollection::new(palettes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encodings::fixtures::{sample, sparse};
+
+    #[test]
+    fn color_index_by_role_name() {
+        for value in 0..=7 {
+            let color = ColorIndex::from_integer(value).unwrap();
+            let json = serde_json::to_string(&color).unwrap();
+            assert_eq!(json, format!("\"{}\"", color.role_name()));
+            assert_eq!(serde_json::from_str::<ColorIndex>(&json).unwrap(), color);
+        }
+        assert_eq!(serde_json::to_string(&ColorIndex::ShirtAccent1).unwrap(), "\"ShirtAccent1\"");
+    }
+
+    #[test]
+    fn color_index_rejects_unknown_roles() {
+        for json in ["\"Shirt\"", "\"skin\"", "8", "-1"] {
+            assert!(serde_json::from_str::<ColorIndex>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn image_as_digit_rows() {
+        for image in [sample(), sparse(), IndexedImage::new()] {
+            let json = serde_json::to_string(&image).unwrap();
+            assert_eq!(serde_json::from_str::<IndexedImage<256, 16>>(&json).unwrap(), image);
+        }
+        let rows: Vec<String> = serde_json::from_str(&serde_json::to_string(&sparse()).unwrap()).unwrap();
+        assert_eq!(rows.len(), 16);
+        assert_eq!(rows[6], "0000000000001000");
+    }
+
+    #[test]
+    fn image_rejects_bad_rows() {
+        let row = "0".repeat(16);
+        let rows = |rows: Vec<String>| serde_json::to_string(&rows).unwrap();
+
+        // a row of the wrong length
+        let mut short = vec![row.clone(); 16];
+        short[3] = "0".repeat(15);
+        // the digit 8
+        let mut eight = vec![row.clone(); 16];
+        eight[0] = format!("8{}", "0".repeat(15));
+
+        for json in [rows(short), rows(eight), rows(vec![row.clone(); 15]), rows(vec![row; 17])] {
+            assert!(serde_json::from_str::<IndexedImage<256, 16>>(&json).is_err(), "{json}");
+        }
+    }
+
+    fn translucent() -> Palette<u8> {
+        let mut palette = Palette::default();
+        palette[ColorIndex::Skin] = image::Rgba([10, 20, 30, 128]);
+        palette
+    }
+
+    #[test]
+    fn palette_packs() {
+        // opaque palettes leave out the alpha bytes
+        let opaque = Palette::<u8>::default().to_bytes();
+        assert_eq!(opaque.len(), 21);
+        assert_eq!(Palette::<u8>::from_bytes(&opaque), Some(Palette::default()));
+
+        let alpha = translucent().to_bytes();
+        assert_eq!(alpha.len(), 28);
+        assert_eq!(alpha[21 + 2], 128);
+        assert_eq!(Palette::<u8>::from_bytes(&alpha), Some(translucent()));
+
+        for length in [0, 20, 22, 27, 29] {
+            assert_eq!(Palette::<u8>::from_bytes(&vec![0; length]), None, "{length}");
+        }
+    }
+
+    #[test]
+    fn palette_as_hex_colors() {
+        let json = serde_json::to_string(&translucent()).unwrap();
+        let colors: Vec<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(colors[0], "#000000");
+        assert_eq!(colors[2], "#0a141e80");
+        assert_eq!(serde_json::from_str::<Palette<u8>>(&json).unwrap(), translucent());
+
+        let mut collection = PaletteCollection::default();
+        collection[1] = translucent();
+        let json = serde_json::to_string(&collection).unwrap();
+        let decoded: PaletteCollection<u8> = serde_json::from_str(&json).unwrap();
+        for (a, b) in decoded.iter().zip(collection.iter()) {
+            assert_eq!(a, b);
+        }
+
+        assert!(serde_json::from_str::<Palette<u8>>(r##"["#12345"]"##).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Oklab, Palette, PaletteCollection};
+
+/// Changes applied to every color of a palette, all in Oklab so lightness stays put while hues turn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteAdjustment {
+    /// degrees to turn every hue
+    pub hue: f32,
+    /// chroma multiplier, 0 is grey
+    pub saturation: f32,
+    /// lightness multiplier
+    pub brightness: f32,
+}
+
+impl Default for PaletteAdjustment {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+impl PaletteAdjustment {
+    /// cooler, paler and a little lighter
+    pub const WINTER: Self = Self { hue: -20.0, saturation: 0.6, brightness: 1.05 };
+    /// bluer, duller and darker
+    pub const NIGHT: Self = Self { hue: -35.0, saturation: 0.5, brightness: 0.6 };
+
+    /// colors pushed outside of sRGB lose chroma until they fit (see [`Oklab::fit_gamut`]),
+    /// so turning a hue and back can come out duller
+    pub fn apply(&self, color: &image::Rgba<u8>) -> image::Rgba<u8> {
+        let lab = Oklab::from_rgba(color).rotate_hue(self.hue);
+        Oklab {
+            l: (lab.l * self.brightness).clamp(0.0, 1.0),
+            a: lab.a * self.saturation,
+            b: lab.b * self.saturation,
+        }.fit_gamut().to_rgba(color.0[3])
+    }
+}
+
+impl<const C: usize> Palette<u8, C> {
+    /// every color with its alpha kept
+    pub fn map_colors(&self, f: impl FnMut(&image::Rgba<u8>) -> image::Rgba<u8>) -> Self {
+        Palette::new(self.colors().each_ref().map(f))
+    }
+
+    pub fn adjust(&self, adjustment: &PaletteAdjustment) -> Self {
+        self.map_colors(|color| adjustment.apply(color))
+    }
+
+    pub fn rotate_hue(&self, degrees: f32) -> Self {
+        self.adjust(&PaletteAdjustment { hue: degrees, ..Default::default() })
+    }
+
+    pub fn scale_saturation(&self, factor: f32) -> Self {
+        self.adjust(&PaletteAdjustment { saturation: factor, ..Default::default() })
+    }
+
+    pub fn scale_brightness(&self, factor: f32) -> Self {
+        self.adjust(&PaletteAdjustment { brightness: factor, ..Default::default() })
+    }
+
+    /// Blends every color toward the same index of `other` in Oklab, so the colors in between don't go muddy.
+    /// `t` of 0 is `self` and 1 is `other`, alpha is blended linearly
+    pub fn lerp(&self, other: &Palette<u8, C>, t: f32) -> Self {
+        let colors: [image::Rgba<u8>; C] = std::array::from_fn(|i| {
+            let (from, to) = (&self.colors()[i], &other.colors()[i]);
+            let alpha = from.0[3] as f32 + (to.0[3] as f32 - from.0[3] as f32) * t;
+            Oklab::from_rgba(from).lerp(&Oklab::from_rgba(to), t).fit_gamut().to_rgba(alpha.round().clamp(0.0, 255.0) as u8)
+        });
+        Palette::new(colors)
+    }
+}
+
+impl<T, const C: usize> PaletteCollection<T, C> {
+    /// a new collection from every palette, in palette order
+    pub fn map(&self, f: impl FnMut(&Palette<T, C>) -> Palette<T, C>) -> Self {
+        PaletteCollection::new(self.palettes.each_ref().map(f))
+    }
+}
+
+impl<const C: usize> PaletteCollection<u8, C> {
+    /// ex. `collection.adjust(&PaletteAdjustment::NIGHT)` for night variants of all 8 palettes
+    pub fn adjust(&self, adjustment: &PaletteAdjustment) -> Self {
+        self.map(|palette| palette.adjust(adjustment))
+    }
+
+    /// every palette blended toward the one with the same id in `other`, see [`Palette::lerp`]
+    pub fn lerp(&self, other: &PaletteCollection<u8, C>, t: f32) -> Self {
+        let mut others = other.iter();
+        self.map(|palette| others.next().map_or_else(|| palette.clone(), |to| palette.lerp(to, t)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ColorIndex, Oklab};
+
+    fn palettes() -> Vec<Palette<u8>> {
+        let mut translucent = Palette::default();
+        translucent[ColorIndex::Skin] = image::Rgba([200, 150, 130, 128]);
+        let mut palettes = vec![Palette::default(), translucent];
+        palettes.extend(PaletteCollection::<u8>::from_seed(3).iter().cloned());
+        palettes
+    }
+
+    #[test]
+    fn neutral_adjustments_change_nothing() {
+        for palette in palettes() {
+            assert_eq!(palette.adjust(&PaletteAdjustment::default()), palette);
+            assert_eq!(palette.rotate_hue(0.0), palette);
+            assert_eq!(palette.scale_saturation(1.0), palette);
+            assert_eq!(palette.scale_brightness(1.0), palette);
+        }
+    }
+
+    #[test]
+    fn lerp_endpoints() {
+        let night = Palette::<u8>::default().adjust(&PaletteAdjustment::NIGHT);
+        for palette in palettes() {
+            assert_eq!(palette.lerp(&night, 0.0), palette);
+            assert_eq!(palette.lerp(&night, 1.0), night);
+        }
+    }
+
+    #[test]
+    fn adjustments_keep_alpha() {
+        let translucent = &palettes()[1];
+        for adjustment in [PaletteAdjustment::WINTER, PaletteAdjustment::NIGHT] {
+            assert_eq!(translucent.adjust(&adjustment)[ColorIndex::Skin].0[3], 128);
+        }
+    }
+
+    #[test]
+    fn zero_saturation_is_grey() {
+        let grey = Palette::<u8>::default().scale_saturation(0.0);
+        for color in grey.colors() {
+            let lab = Oklab::from_rgba(color);
+            assert!(lab.a.abs() < 0.01 && lab.b.abs() < 0.01, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn night_is_never_lighter() {
+        let palette = Palette::<u8>::default();
+        let night = palette.adjust(&PaletteAdjustment::NIGHT);
+        for (day, night) in palette.colors().iter().zip(night.colors()) {
+            assert!(Oklab::from_rgba(night).l <= Oklab::from_rgba(day).l + 0.001);
+        }
+    }
+
+    #[test]
+    fn collections_adjust_every_palette() {
+        let collection = PaletteCollection::<u8>::from_seed(3);
+        let night = collection.adjust(&PaletteAdjustment::NIGHT);
+        for (palette, adjusted) in collection.iter().zip(night.iter()) {
+            assert_eq!(palette.adjust(&PaletteAdjustment::NIGHT), *adjusted);
+        }
+        for (blended, adjusted) in collection.lerp(&night, 1.0).iter().zip(night.iter()) {
+            assert_eq!(blended, adjusted);
+        }
+    }
+}